@@ -0,0 +1,556 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::panic;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::io::{BufReader, BufWriter, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::{self, Sleep};
+use url::{ParseError, Url};
+
+use crate::body::{Decoded, Decoder, Framing};
+use crate::client::Client;
+use crate::config::Config;
+use crate::consts::DEF_HAPPY_EYEBALLS_DELAY;
+use crate::dial::{interleave, ConnectError};
+use crate::err::{HttpError, Timeout};
+use crate::header::HeaderMap;
+use crate::limit::{Limit, Limits};
+use crate::method::Method;
+use crate::payload::Payload;
+use crate::pool::Pool;
+use crate::response::{decoded_reader, HeadParser, MessageHead, Response};
+#[cfg(any(feature = "async-rustls", all(feature = "async-native-tls", not(feature = "rustls"))))]
+use crate::tls;
+use crate::transport::{default_port, dial_host};
+use crate::{is_stale, Rewind, HTTP};
+
+/// Byte stream the async client sends requests over
+pub trait AsyncConnection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncConnection for T {}
+
+/// Buffered connection, as kept in the pool of an `AsyncClient`
+pub type AsyncConn = BufReader<TimedIo>;
+
+/// Client sending requests on the tokio runtime over pooled connections
+///
+/// Settings are taken from the `Client` it is created from, every request
+/// inherits them. Proxies and custom transports are not available here:
+/// proxies read from the environment by `Client::from_env` are skipped and
+/// requests connect directly, other proxies fail the request.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use std::time::Duration;
+///
+/// let mut client = knock::Client::new();
+/// client.timeout(Duration::from_secs(10));
+/// let client = knock::AsyncClient::from(client);
+///
+/// async fn date(client: &knock::AsyncClient) -> Result<String, knock::HttpError> {
+///     let mut http = client.http("https://example.com/api/date")?;
+///     let response = http.get().send_async().await?;
+///     Ok(response.text())
+/// }
+/// ```
+///
+#[derive(Clone)]
+pub struct AsyncClient {
+    client: Client,
+    pool: Pool<AsyncConn>,
+}
+
+impl AsyncClient {
+    /// Client instance with default settings and pool limits
+    pub fn new() -> AsyncClient {
+        AsyncClient::from(Client::new())
+    }
+
+    /// Number of idle connections kept in the pool
+    pub fn idle_connections(&self) -> usize {
+        self.pool.idle()
+    }
+
+    /// Close every idle connection
+    pub fn clear(&self) {
+        self.pool.clear();
+    }
+
+    /// Create a request bound to this client's connection pool
+    ///
+    /// The request can still be sent with `send`, it then uses a connection
+    /// of its own and closes it afterwards.
+    pub fn http(&self, url: &str) -> Result<HTTP, HttpError> {
+        let mut http = self.client.http(url)?;
+        http.pool = None;
+        http.async_pool = Some(self.pool.clone());
+        Ok(http)
+    }
+}
+
+impl From<Client> for AsyncClient {
+    fn from(client: Client) -> AsyncClient {
+        AsyncClient {
+            pool: Pool::with_limits_of(&client.pool),
+            client,
+        }
+    }
+}
+
+impl Default for AsyncClient {
+    fn default() -> AsyncClient {
+        AsyncClient::new()
+    }
+}
+
+impl HTTP {
+    /// Send the request on the tokio runtime and read the whole response
+    ///
+    /// Request building, redirects, limits and decompression work as for
+    /// `send`, except that proxies from the environment are skipped.
+    /// Dropping the returned future cancels the request, the connection it
+    /// was using is closed.
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// async fn date() -> Result<String, knock::HttpError> {
+    ///     let mut http = knock::HTTP::new("https://example.com/api/date")?;
+    ///     let response = http.get().send_async().await?;
+    ///     Ok(response.text())
+    /// }
+    /// ```
+    ///
+    pub async fn send_async(&mut self) -> Result<Response, HttpError> {
+        let mut rewind = self.start();
+        // proxies from the environment are skipped rather than failing
+        let proxies = self.config.proxies.clone();
+        self.config.proxies.retain(|proxy| !proxy.is_from_env());
+
+        let result = match self.deadline {
+            Some(deadline) => time::timeout_at(deadline.into(), self.follow_redirects_async(&mut rewind))
+                .await
+                .unwrap_or(Err(HttpError::Timeout(Timeout::Total))),
+            None => self.follow_redirects_async(&mut rewind).await,
+        };
+
+        self.config.proxies = proxies;
+        self.rewind(rewind);
+        result
+    }
+
+    async fn follow_redirects_async(&mut self, rewind: &mut Rewind) -> Result<Response, HttpError> {
+        let mut chain: Vec<Url> = Vec::new();
+        let streamed = self.upload.is_some();
+
+        loop {
+            let mut response = self.send_once_async().await?;
+
+            if !self.next_hop(response.status, &response.header, &mut chain, streamed, rewind)? {
+                response.redirects = chain;
+                return Ok(response);
+            }
+        }
+    }
+
+    async fn send_once_async(&mut self) -> Result<Response, HttpError> {
+        let url = self.url.host_str().ok_or(ParseError::EmptyHost)?;
+        self.host = url.to_string();
+        let keep_alive = self.async_pool.is_some();
+        let (request, mut payload) = self.build_request(keep_alive)?;
+        let key = self.pool_key();
+        let pool = self.async_pool.clone();
+        let method = self.method.clone();
+        let config = &self.config;
+        let deadline = self.deadline;
+
+        // an idle connection may have been closed by the server meanwhile, in
+        // that case the request is retried once on a fresh connection unless
+        // its body was streamed
+        let mut idle = pool.as_ref().and_then(|pool| pool.checkout(&key));
+        let (mut conn, head) = loop {
+            let reused = idle.is_some();
+            let mut conn = match idle.take() {
+                Some(conn) => conn,
+                None => connect(config, &self.url, deadline).await?,
+            };
+            match round_trip(&mut conn, &request, &mut payload, &method, config, deadline).await {
+                Ok(head) => break (conn, head),
+                Err(HttpError::IO(ref err)) if reused && is_stale(err) && payload.is_replayable() => {}
+                Err(err) => return Err(err),
+            }
+        };
+
+        let (body, trailer) = read_body(&mut conn, head.framing, &config.limits)
+            .await
+            .map_err(|err| HttpError::timed_out(err, Timeout::Read, deadline))?;
+        if let Some(pool) = pool.filter(|_| head.reusable) {
+            pool.checkin(key, conn);
+        }
+
+        let mut response = Response::from_head(&head.head)?;
+        response.body = body;
+        response.trailer = trailer;
+        let decompress = !config.disable_decompression;
+        let body = {
+            let wire = Box::new(&response.body[..]);
+            let (mut reader, decoded) =
+                decoded_reader(
+                &response.header,
+                head.framing,
+                wire,
+                decompress,
+                config.limits.decoded_size(true),
+            )?;
+            match decoded {
+                true => {
+                    let mut body = Vec::new();
+                    reader.read_to_end(&mut body)?;
+                    Some(body)
+                }
+                false => None,
+            }
+        };
+        if let Some(body) = body {
+            response.body = body;
+            response.decoded = true;
+        }
+        Ok(response)
+    }
+}
+
+/// Write the request head and body, then read the head of the response
+async fn round_trip(
+    conn: &mut AsyncConn,
+    request: &[u8],
+    payload: &mut Payload,
+    method: &Method,
+    config: &Config,
+    deadline: Option<Instant>,
+) -> Result<MessageHead, HttpError> {
+    let write_err = |err| HttpError::timed_out(err, Timeout::Write, deadline);
+    let read_err = |err| HttpError::timed_out(err, Timeout::Read, deadline);
+
+    conn.get_mut().set_timeouts(config.read_timeout, config.write_timeout);
+    {
+        let mut out = BufWriter::new(conn.get_mut());
+        out.write_all(request).await.map_err(write_err)?;
+        payload.write_to_async(&mut out).await.map_err(write_err)?;
+        out.flush().await.map_err(write_err)?;
+    }
+
+    // nothing at all coming back means the peer already closed the connection
+    if conn.fill_buf().await.map_err(read_err)?.is_empty() {
+        let err = io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed before response");
+        return Err(err.into());
+    }
+    read_message_head(conn, method, &config.limits).await.map_err(read_err)
+}
+
+/// Head of the final response, interim 1xx responses are skipped
+async fn read_message_head<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    method: &Method,
+    limits: &Limits,
+) -> io::Result<MessageHead> {
+    let mut parser = HeadParser::new(method, limits);
+    loop {
+        let input = reader.fill_buf().await?;
+        let (used, head) = parser.parse(input)?;
+        reader.consume(used);
+        if let Some(head) = head {
+            return Ok(head);
+        }
+    }
+}
+
+/// Body of a response, without the chunked framing, and its trailer
+async fn read_body<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    framing: Framing,
+    limits: &Limits,
+) -> io::Result<(Vec<u8>, HeaderMap)> {
+    // the body is read into memory, it is always bounded
+    let max = limits.body_size(true).unwrap_or(u64::MAX);
+    // refuse an announced body that is too large before reading any of it
+    if let Framing::Length(len) = framing {
+        if len > max {
+            return Err(Limit::BodySize(max).into());
+        }
+    }
+
+    let mut decoder = Decoder::new(framing, limits);
+    let mut body = Vec::new();
+    while !decoder.is_done() {
+        let input = reader.fill_buf().await?;
+        match decoder.decode(input, usize::MAX)? {
+            Decoded::Body(n) => {
+                if n as u64 > max - body.len() as u64 {
+                    return Err(Limit::BodySize(max).into());
+                }
+                body.extend_from_slice(&input[..n]);
+                reader.consume(n);
+            }
+            Decoded::Framing(n) => reader.consume(n),
+        }
+    }
+    Ok((body, decoder.into_trailer()))
+}
+
+/// Open a connection to the server of `url`, TLS included for https
+async fn connect(config: &Config, url: &Url, deadline: Option<Instant>) -> Result<AsyncConn, HttpError> {
+    if config.transport.is_some() {
+        return Err(HttpError::MissingFeature("send_async does not support custom transports".into()));
+    }
+    if config.proxy_for(url).is_some() {
+        return Err(HttpError::Proxy("send_async does not support proxies".into()));
+    }
+
+    let scheme = url.scheme();
+    let host = dial_host(url).ok_or(ParseError::EmptyHost)?;
+    let port = url.port().unwrap_or_else(|| default_port(scheme));
+
+    let connect_by = config.connect_timeout.map(|timeout| Instant::now() + timeout);
+    let io = open(config, scheme, &host, port, connect_by, deadline).await?;
+    Ok(BufReader::new(TimedIo::new(io)))
+}
+
+/// Open the connection, giving up at `connect_by`
+async fn open(
+    config: &Config,
+    scheme: &str,
+    host: &str,
+    port: u16,
+    connect_by: Option<Instant>,
+    deadline: Option<Instant>,
+) -> Result<Box<dyn AsyncConnection>, HttpError> {
+    #[cfg(unix)]
+    {
+        if let Some(ref unix) = config.unix_socket {
+            if scheme == "https" {
+                let err = io::Error::new(io::ErrorKind::InvalidInput, "TLS over Unix sockets is not supported");
+                return Err(err.into());
+            }
+            let connecting = async {
+                tokio::net::UnixStream::connect(unix.path())
+                    .await
+                    .map_err(|err| HttpError::timed_out(err, Timeout::Connect, deadline))
+            };
+            return Ok(Box::new(connect_within(connect_by, connecting).await?));
+        }
+    }
+
+    let stream = tcp_connect(config, host, port, connect_by, deadline).await?;
+    match scheme == "https" {
+        true => connect_within(connect_by, tls_connect(config, host, stream)).await,
+        false => Ok(Box::new(stream)),
+    }
+}
+
+/// Run a step of connecting, failing with `Timeout::Connect` at `connect_by`
+async fn connect_within<T, F>(connect_by: Option<Instant>, step: F) -> Result<T, HttpError>
+where
+    F: Future<Output = Result<T, HttpError>>,
+{
+    match connect_by {
+        Some(connect_by) => time::timeout_at(connect_by.into(), step)
+            .await
+            .unwrap_or(Err(HttpError::Timeout(Timeout::Connect))),
+        None => step.await,
+    }
+}
+
+/// Resolve the host and race connections to its addresses
+async fn tcp_connect(
+    config: &Config,
+    host: &str,
+    port: u16,
+    connect_by: Option<Instant>,
+    deadline: Option<Instant>,
+) -> Result<TcpStream, HttpError> {
+    let connect_err = |err| HttpError::timed_out(err, Timeout::Connect, deadline);
+
+    // resolvers block, keep them off the runtime threads
+    let resolving = async {
+        let config = config.clone();
+        let host = host.to_string();
+        let addrs = tokio::task::spawn_blocking(move || config.resolve(&host, port)).await;
+        addrs.map_err(io::Error::other).and_then(|res| res).map_err(connect_err)
+    };
+    let addrs = connect_within(connect_by, resolving).await?;
+    let delay = config
+        .happy_eyeballs_delay
+        .unwrap_or_else(|| Duration::from_millis(DEF_HAPPY_EYEBALLS_DELAY));
+
+    let stream = match happy_eyeballs(addrs, delay, connect_by).await {
+        Ok(stream) => stream,
+        Err(ref err) if err.is_timeout() => return Err(connect_err(io::ErrorKind::TimedOut.into())),
+        Err(err) => return Err(HttpError::Connect(err)),
+    };
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Connect to the first address answering, racing attempts per RFC 8305
+///
+/// The schedule of `dial::happy_eyeballs`: addresses alternate between IPv6
+/// and IPv4, a new attempt starts every `delay` or as soon as the previous
+/// one failed. Attempts still running once one succeeds are aborted.
+async fn happy_eyeballs(
+    addrs: Vec<SocketAddr>,
+    delay: Duration,
+    connect_by: Option<Instant>,
+) -> Result<TcpStream, ConnectError> {
+    let mut queue = interleave(addrs).into_iter();
+    let mut attempts = Vec::new();
+    let mut racing = JoinSet::new();
+
+    loop {
+        if let Some(addr) = queue.next() {
+            racing.spawn(async move { (addr, TcpStream::connect(addr).await) });
+        }
+
+        // wait for an attempt to settle, at most until the next one is due
+        let next_start = Instant::now() + delay;
+        let wake = match queue.len() {
+            0 => connect_by,
+            _ => Some(connect_by.map_or(next_start, |connect_by| connect_by.min(next_start))),
+        };
+        let settled = match wake {
+            Some(wake) => match time::timeout_at(wake.into(), racing.join_next()).await {
+                Ok(settled) => settled,
+                Err(_) if connect_by.is_some_and(|connect_by| Instant::now() >= connect_by) => {
+                    return Err(ConnectError::new(attempts, true));
+                }
+                Err(_) => continue,
+            },
+            None => racing.join_next().await,
+        };
+
+        match settled {
+            None => return Err(ConnectError::new(attempts, false)),
+            Some(Ok((_, Ok(stream)))) => return Ok(stream),
+            Some(Ok((addr, Err(err)))) => attempts.push((addr, err)),
+            // the set is owned here, its tasks only end by finishing or panicking
+            Some(Err(err)) => panic::resume_unwind(err.into_panic()),
+        }
+    }
+}
+
+#[cfg(feature = "async-rustls")]
+async fn tls_connect(config: &Config, host: &str, stream: TcpStream) -> Result<Box<dyn AsyncConnection>, HttpError> {
+    let connector = tokio_rustls::TlsConnector::from(tls::client_config(config)?);
+    let stream = connector
+        .connect(tls::server_name(host)?, stream)
+        .await
+        .map_err(tls::handshake_error)?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(all(feature = "async-native-tls", not(feature = "rustls")))]
+async fn tls_connect(config: &Config, host: &str, stream: TcpStream) -> Result<Box<dyn AsyncConnection>, HttpError> {
+    let connector = tokio_native_tls::TlsConnector::from(tls::connector(config)?);
+    Ok(Box::new(connector.connect(host, stream).await?))
+}
+
+#[cfg(not(any(feature = "async-rustls", all(feature = "async-native-tls", not(feature = "rustls")))))]
+async fn tls_connect(_config: &Config, _host: &str, _stream: TcpStream) -> Result<Box<dyn AsyncConnection>, HttpError> {
+    Err(HttpError::MissingFeature(
+        "Lib not compiled with feature async-native-tls or async-rustls active".into(),
+    ))
+}
+
+/// Connection failing a read or write that makes no progress in time
+///
+/// The async counterpart of socket timeouts, set again for every request.
+pub struct TimedIo {
+    io: Box<dyn AsyncConnection>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    read_timer: Option<Pin<Box<Sleep>>>,
+    write_timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl TimedIo {
+    fn new(io: Box<dyn AsyncConnection>) -> TimedIo {
+        TimedIo {
+            io,
+            read_timeout: None,
+            write_timeout: None,
+            read_timer: None,
+            write_timer: None,
+        }
+    }
+
+    fn set_timeouts(&mut self, read: Option<Duration>, write: Option<Duration>) {
+        self.read_timeout = read;
+        self.write_timeout = write;
+        self.read_timer = None;
+        self.write_timer = None;
+    }
+}
+
+/// Whether `timeout` passed since the operation started waiting
+fn expired(timer: &mut Option<Pin<Box<Sleep>>>, timeout: Option<Duration>, cx: &mut Context<'_>) -> bool {
+    match timeout {
+        Some(timeout) => timer
+            .get_or_insert_with(|| Box::pin(time::sleep(timeout)))
+            .as_mut()
+            .poll(cx)
+            .is_ready(),
+        None => false,
+    }
+}
+
+/// Settle a poll of the connection, failing it once it waited too long
+fn timed<T>(
+    poll: Poll<io::Result<T>>,
+    timer: &mut Option<Pin<Box<Sleep>>>,
+    timeout: Option<Duration>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<T>> {
+    match poll {
+        Poll::Pending if expired(timer, timeout, cx) => {
+            *timer = None;
+            Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, "operation timed out")))
+        }
+        Poll::Pending => Poll::Pending,
+        ready => {
+            *timer = None;
+            ready
+        }
+    }
+}
+
+impl AsyncRead for TimedIo {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.io).poll_read(cx, buf);
+        timed(poll, &mut this.read_timer, this.read_timeout, cx)
+    }
+}
+
+impl AsyncWrite for TimedIo {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.io).poll_write(cx, buf);
+        timed(poll, &mut this.write_timer, this.write_timeout, cx)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.io).poll_flush(cx);
+        timed(poll, &mut this.write_timer, this.write_timeout, cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
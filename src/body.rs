@@ -0,0 +1,306 @@
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+use crate::header::HeaderMap;
+use crate::limit::{Limit, Limits};
+
+/// How the end of a response body is determined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// No body at all (HEAD, 204, 304)
+    Empty,
+    /// `Content-Length` delimited body
+    Length(u64),
+    /// `Transfer-Encoding: chunked` body
+    Chunked,
+    /// Body ends when the server closes the connection
+    Eof,
+}
+
+enum State {
+    Length(u64),
+    ChunkSize,
+    Chunk(u64),
+    ChunkEnd,
+    Trailer,
+    Eof,
+    Done,
+}
+
+/// What `Decoder::decode` found at the start of its input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decoded {
+    /// That many bytes are body content
+    Body(usize),
+    /// That many bytes were framing, consumed without yielding content
+    Framing(usize),
+}
+
+/// Body framing state machine, fed whatever the connection has buffered
+///
+/// It does no IO itself, blocking and async connections drive the same
+/// decoder. Chunk extensions are ignored and trailers are collected, bounded
+/// by the limits on the response head.
+pub struct Decoder {
+    state: State,
+    line: Vec<u8>,
+    trailer: HeaderMap,
+    trailer_size: usize,
+    trailer_fields: usize,
+    limits: Limits,
+}
+
+impl Decoder {
+    pub fn new(framing: Framing, limits: &Limits) -> Decoder {
+        let state = match framing {
+            Framing::Empty | Framing::Length(0) => State::Done,
+            Framing::Length(len) => State::Length(len),
+            Framing::Chunked => State::ChunkSize,
+            Framing::Eof => State::Eof,
+        };
+
+        Decoder {
+            state,
+            line: Vec::new(),
+            trailer: HeaderMap::new(),
+            trailer_size: 0,
+            trailer_fields: 0,
+            limits: *limits,
+        }
+    }
+
+    /// Whether the whole body has been decoded
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// Trailer fields sent after the last chunk
+    pub fn into_trailer(self) -> HeaderMap {
+        self.trailer
+    }
+
+    /// Look at the start of `input`, empty at the end of the stream
+    ///
+    /// At most `max` bytes of content are taken at once, the caller consumes
+    /// as many bytes of `input` as returned.
+    pub fn decode(&mut self, input: &[u8], max: usize) -> io::Result<Decoded> {
+        let end = input.is_empty();
+        match self.state {
+            State::Done => Ok(Decoded::Framing(0)),
+            State::Eof if end => {
+                self.state = State::Done;
+                Ok(Decoded::Framing(0))
+            }
+            State::Eof => Ok(Decoded::Body(input.len().min(max))),
+            State::Length(_) if end => Err(eof("truncated body")),
+            State::Length(remaining) => {
+                let n = remaining.min(input.len().min(max) as u64);
+                let remaining = remaining - n;
+                self.state = if remaining == 0 { State::Done } else { State::Length(remaining) };
+                Ok(Decoded::Body(n as usize))
+            }
+            _ if end => Err(eof("truncated chunked body")),
+            State::Chunk(remaining) => {
+                let n = remaining.min(input.len().min(max) as u64);
+                let remaining = remaining - n;
+                self.state = if remaining == 0 { State::ChunkEnd } else { State::Chunk(remaining) };
+                Ok(Decoded::Body(n as usize))
+            }
+            State::ChunkSize | State::ChunkEnd | State::Trailer => {
+                let used = match input.iter().position(|&b| b == b'\n') {
+                    Some(pos) => pos + 1,
+                    None => input.len(),
+                };
+                self.line.extend_from_slice(&input[..used]);
+                let max = self.limits.max_header_size;
+                if matches!(self.state, State::Trailer) && self.trailer_size + self.line.len() > max + 2 {
+                    return Err(Limit::HeaderSize(max).into());
+                }
+                if self.line.ends_with(b"\n") {
+                    let line = String::from_utf8_lossy(&mem::take(&mut self.line)).into_owned();
+                    self.framing_line(line.trim_end_matches(['\r', '\n']))?;
+                } else if self.line.len() > max {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "chunked framing line too long"));
+                }
+                Ok(Decoded::Framing(used))
+            }
+        }
+    }
+
+    fn framing_line(&mut self, line: &str) -> io::Result<()> {
+        self.state = match self.state {
+            State::ChunkSize => match chunk_size(line)? {
+                0 => State::Trailer,
+                size => State::Chunk(size),
+            },
+            // every chunk is followed by CRLF
+            State::ChunkEnd if line.is_empty() => State::ChunkSize,
+            State::ChunkEnd => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "missing CRLF after chunk"));
+            }
+            State::Trailer if line.is_empty() => State::Done,
+            State::Trailer => {
+                // trailer fields count against the limits on the head
+                self.trailer_size += line.len() + 2;
+                if self.trailer_size > self.limits.max_header_size {
+                    return Err(Limit::HeaderSize(self.limits.max_header_size).into());
+                }
+                self.trailer_fields += 1;
+                if self.trailer_fields > self.limits.max_headers {
+                    return Err(Limit::HeaderCount(self.limits.max_headers).into());
+                }
+                push_field(&mut self.trailer, line);
+                State::Trailer
+            }
+            _ => unreachable!("not on a framing line"),
+        };
+        Ok(())
+    }
+}
+
+/// Reader that yields only the decoded body of a response message
+///
+/// Trailers are available from `into_trailer` once the body has been read
+/// to the end.
+pub struct BodyReader<R> {
+    inner: R,
+    decoder: Decoder,
+}
+
+impl<R: BufRead> BodyReader<R> {
+    pub fn new(inner: R, framing: Framing, limits: &Limits) -> BodyReader<R> {
+        BodyReader {
+            inner,
+            decoder: Decoder::new(framing, limits),
+        }
+    }
+
+    /// Trailer fields sent after the last chunk
+    pub fn into_trailer(self) -> HeaderMap {
+        self.decoder.into_trailer()
+    }
+
+    /// Whether the whole body has been read
+    pub fn is_done(&self) -> bool {
+        self.decoder.is_done()
+    }
+
+    /// Underlying reader and trailer, positioned after the body once done
+    pub fn into_parts(self) -> (R, HeaderMap) {
+        (self.inner, self.decoder.into_trailer())
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while !self.decoder.is_done() {
+            let input = self.inner.fill_buf()?;
+            match self.decoder.decode(input, buf.len())? {
+                Decoded::Body(n) => {
+                    buf[..n].copy_from_slice(&input[..n]);
+                    self.inner.consume(n);
+                    return Ok(n);
+                }
+                Decoded::Framing(n) => self.inner.consume(n),
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Size from a chunk-size line, chunk extensions are ignored
+fn chunk_size(line: &str) -> io::Result<u64> {
+    // chunk-size [ ; chunk-ext ]
+    let size = line.split(';').next().unwrap_or("").trim();
+    u64::from_str_radix(size, 16).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("invalid chunk size: {:?}", line))
+    })
+}
+
+/// Add a `name: value` line to `fields`, anything else is skipped
+fn push_field(fields: &mut HeaderMap, line: &str) {
+    if let Some(pos) = line.find(':') {
+        fields.push(line[..pos].trim(), line[pos + 1..].trim());
+    }
+}
+
+fn eof(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BodyReader, Decoded, Decoder, Framing};
+    use crate::limit::{Limit, Limits};
+    use std::io::Read;
+
+    #[test]
+    fn test_chunked_with_extensions_and_trailer() {
+        let raw = "4;name=value\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\nnext";
+        let mut reader = BodyReader::new(raw.as_bytes(), Framing::Chunked, &Limits::default());
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+
+        assert_eq!(body, "Wikipedia");
+        assert_eq!(reader.into_trailer().get("Expires"), Some("never"));
+    }
+
+    #[test]
+    fn test_length_stops_at_boundary() {
+        let mut reader = BodyReader::new("hello world".as_bytes(), Framing::Length(5), &Limits::default());
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+
+        assert_eq!(body, "hello");
+    }
+
+    #[test]
+    fn test_truncated_chunk_is_error() {
+        let mut reader = BodyReader::new("a\r\nshort".as_bytes(), Framing::Chunked, &Limits::default());
+        let mut body = String::new();
+
+        assert!(reader.read_to_string(&mut body).is_err());
+    }
+
+    #[test]
+    fn test_decoder_fed_byte_by_byte() {
+        let raw = b"3\r\nabc\r\n2;x=y\r\nde\r\n0\r\nDigest: ok\r\n\r\n";
+        let mut decoder = Decoder::new(Framing::Chunked, &Limits::default());
+        let mut body = Vec::new();
+        for byte in raw.chunks(1) {
+            if let Decoded::Body(n) = decoder.decode(byte, 8).unwrap() {
+                body.extend_from_slice(&byte[..n]);
+            }
+        }
+
+        assert!(decoder.is_done());
+        assert_eq!(body, b"abcde");
+        assert_eq!(decoder.into_trailer().get("digest"), Some("ok"));
+    }
+
+    #[test]
+    fn test_trailer_is_bounded_by_head_limits() {
+        let read = |raw: &str, limits: Limits| {
+            let mut body = Vec::new();
+            let err = BodyReader::new(raw.as_bytes(), Framing::Chunked, &limits).read_to_end(&mut body).unwrap_err();
+            Limit::from_io(&err)
+        };
+
+        let many = format!("0\r\n{}\r\n", "X-Field: 1\r\n".repeat(3));
+        let limits = Limits { max_headers: 2, ..Limits::default() };
+        assert_eq!(read(&many, limits), Some(Limit::HeaderCount(2)));
+
+        // an endless trailer line fails before the line ends
+        let long = format!("0\r\nX-Padding: {}", "a".repeat(1000));
+        let limits = Limits { max_header_size: 64, ..Limits::default() };
+        assert_eq!(read(&long, limits), Some(Limit::HeaderSize(64)));
+
+        let limits = Limits { max_header_size: 30, ..Limits::default() };
+        assert_eq!(read(&many, limits), Some(Limit::HeaderSize(30)));
+    }
+}
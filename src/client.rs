@@ -0,0 +1,277 @@
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::err::HttpError;
+use crate::limit::Limits;
+use crate::pool::Pool;
+use crate::proxy::{self, Proxy};
+use crate::redirect::Redirect;
+use crate::resolve::{DnsCache, Resolve};
+use crate::tls::{Certificate, Identity};
+use crate::transport::Transport;
+#[cfg(unix)]
+use crate::transport::UnixTransport;
+use crate::HTTP;
+
+/// Long-lived HTTP/1.1 client that keeps connections alive between requests
+///
+/// Idle connections are pooled per (scheme, host, port) and shared between
+/// clones of the same `Client`.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// let client = knock::Client::new();
+/// let mut http = client.http("https://example.com/api/date").unwrap();
+/// http.get().send();
+/// ```
+///
+#[derive(Clone)]
+pub struct Client {
+    pub(crate) pool: Pool,
+    config: Config,
+}
+
+impl Client {
+    /// Client instance with default pool limits
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let client = knock::Client::new();
+    /// ```
+    ///
+    pub fn new() -> Client {
+        Client {
+            pool: Pool::new(),
+            config: Config::default(),
+        }
+    }
+
+    /// Client instance routing requests through the proxies configured in
+    /// the environment (`http_proxy`, `https_proxy`, `all_proxy`, `no_proxy`)
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let client = knock::Client::from_env();
+    /// ```
+    ///
+    pub fn from_env() -> Client {
+        let mut client = Client::new();
+        client.config.proxies = proxy::from_env();
+        client
+    }
+
+    /// Maximum number of idle connections kept across all hosts
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut client = knock::Client::new();
+    /// client.max_idle(16).max_idle_per_host(2);
+    /// ```
+    ///
+    pub fn max_idle(&mut self, max: usize) -> &mut Self {
+        self.pool.set_max_idle(max);
+        self
+    }
+
+    /// Maximum number of idle connections kept for a single (scheme, host, port)
+    pub fn max_idle_per_host(&mut self, max: usize) -> &mut Self {
+        self.pool.set_max_idle_per_host(max);
+        self
+    }
+
+    /// How long an idle connection may stay in the pool before it is closed
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// let mut client = knock::Client::new();
+    /// client.idle_timeout(Duration::from_secs(30));
+    /// ```
+    ///
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.pool.set_idle_timeout(timeout);
+        self
+    }
+
+    /// Default connect timeout for requests created by this client
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// let mut client = knock::Client::new();
+    /// client
+    ///     .connect_timeout(Duration::from_secs(5))
+    ///     .read_timeout(Duration::from_secs(30))
+    ///     .timeout(Duration::from_secs(120));
+    /// ```
+    ///
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Connect to `addr` for `host:port` instead of resolving it
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// let mut client = knock::Client::new();
+    /// client
+    ///     .resolve("api.example.com:443", "10.0.0.5:443".parse().unwrap())
+    ///     .dns_cache(Duration::from_secs(60));
+    /// ```
+    ///
+    pub fn resolve(&mut self, host_port: &str, addr: SocketAddr) -> &mut Self {
+        let addrs = self.config.resolve_overrides.entry(host_port.to_lowercase()).or_default();
+        addrs.push(addr);
+        self
+    }
+
+    /// Resolve host names with a custom resolver
+    pub fn resolver<R: Resolve + 'static>(&mut self, resolver: R) -> &mut Self {
+        self.config.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Keep resolved addresses for `ttl`, shared by every request of this client
+    pub fn dns_cache(&mut self, ttl: Duration) -> &mut Self {
+        self.config.dns_cache = Some(Arc::new(DnsCache::new(ttl)));
+        self
+    }
+
+    /// Whether requests created by this client ask for and decode compressed responses
+    pub fn decompress(&mut self, enabled: bool) -> &mut Self {
+        self.config.disable_decompression = !enabled;
+        self
+    }
+
+    /// Head start given to a connection attempt before the next resolved
+    /// address is tried in parallel
+    pub fn happy_eyeballs_delay(&mut self, delay: Duration) -> &mut Self {
+        self.config.happy_eyeballs_delay = Some(delay);
+        self
+    }
+
+    /// Default read timeout for requests created by this client
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Default write timeout for requests created by this client
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Default overall deadline for requests created by this client
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Default redirect policy for requests created by this client
+    pub fn redirect(&mut self, policy: Redirect) -> &mut Self {
+        self.config.redirect = policy;
+        self
+    }
+
+    /// Default response size limits for requests created by this client
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// Root certificate trusted by requests created by this client
+    pub fn add_root_certificate(&mut self, cert: Certificate) -> &mut Self {
+        self.config.root_certificates.push(cert);
+        self
+    }
+
+    /// Trust only the certificates added with `add_root_certificate`
+    pub fn disable_built_in_roots(&mut self, disable: bool) -> &mut Self {
+        self.config.disable_built_in_roots = disable;
+        self
+    }
+
+    /// Client certificate presented by requests created by this client
+    pub fn identity(&mut self, identity: Identity) -> &mut Self {
+        self.config.identity = Some(identity);
+        self
+    }
+
+    /// Route requests created by this client through a proxy
+    ///
+    /// Several proxies may be added, e.g. one per scheme, the first one
+    /// intercepting a request is used.
+    pub fn proxy(&mut self, proxy: Proxy) -> &mut Self {
+        self.config.proxies.push(proxy);
+        self
+    }
+
+    /// Connect directly, dropping every configured proxy
+    pub fn no_proxy(&mut self) -> &mut Self {
+        self.config.proxies.clear();
+        self
+    }
+
+    /// Send requests created by this client over the Unix domain socket at `path`
+    #[cfg(unix)]
+    pub fn unix_socket<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.config.unix_socket = Some(UnixTransport::new(path));
+        self
+    }
+
+    /// Open connections through a custom transport instead of TCP
+    pub fn transport<T: Transport + 'static>(&mut self, transport: T) -> &mut Self {
+        self.config.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Number of idle connections currently held by the pool
+    pub fn idle_connections(&self) -> usize {
+        self.pool.idle()
+    }
+
+    /// Close every idle connection
+    pub fn clear(&self) {
+        self.pool.clear();
+    }
+
+    /// Create a request bound to this client's connection pool
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let client = knock::Client::new();
+    /// let mut http = client.http("https://example.com/api/date").unwrap();
+    /// http.get().send();
+    /// ```
+    ///
+    pub fn http(&self, url: &str) -> Result<HTTP, HttpError> {
+        let mut http = HTTP::new(url)?;
+        http.pool = Some(self.pool.clone());
+        http.config = self.config.clone();
+        Ok(http)
+    }
+}
+
+impl Default for Client {
+    fn default() -> Client {
+        Client::new()
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::limit::Limits;
+use crate::proxy::Proxy;
+use crate::redirect::Redirect;
+use crate::resolve::{DnsCache, Resolve, SystemResolver};
+use crate::tls::{Certificate, Identity};
+use crate::transport::{authority, Transport};
+#[cfg(unix)]
+use crate::transport::UnixTransport;
+
+/// Connection settings shared by a `Client` and the requests it creates
+#[derive(Clone, Default)]
+pub struct Config {
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub write_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+    /// Head start of a connection attempt before the next address is tried
+    pub happy_eyeballs_delay: Option<Duration>,
+    pub redirect: Redirect,
+    pub danger_accept_invalid_certs: bool,
+    pub limits: Limits,
+    /// Neither advertise nor decode content codings
+    pub disable_decompression: bool,
+    /// Extra roots trusted when verifying servers
+    pub root_certificates: Vec<Certificate>,
+    /// Trust only `root_certificates`
+    pub disable_built_in_roots: bool,
+    /// Client certificate for mutual TLS
+    pub identity: Option<Identity>,
+    /// Addresses dialled instead of resolving a lowercase `host:port`
+    pub resolve_overrides: HashMap<String, Vec<SocketAddr>>,
+    /// Custom resolver, `SystemResolver` when unset
+    pub resolver: Option<Arc<dyn Resolve>>,
+    pub dns_cache: Option<Arc<DnsCache>>,
+    /// Proxies tried in order, the first intercepting a request is used
+    pub proxies: Vec<Proxy>,
+    /// Unix socket to connect to instead of the URL's host
+    #[cfg(unix)]
+    pub unix_socket: Option<UnixTransport>,
+    /// Custom transport, `TcpTransport` when unset
+    pub transport: Option<Arc<dyn Transport>>,
+}
+
+impl Config {
+    /// Proxy a request to `url` has to go through
+    pub fn proxy_for(&self, url: &Url) -> Option<&Proxy> {
+        self.proxies.iter().find(|proxy| proxy.intercepts(url))
+    }
+
+    /// Addresses to connect to for `host:port`
+    ///
+    /// Overrides win over the cache, which wins over the resolver.
+    pub fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addrs) = self.resolve_overrides.get(&authority(host, port).to_lowercase()) {
+            return Ok(addrs.clone());
+        }
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let lookup = || match self.resolver {
+            Some(ref resolver) => resolver.resolve(host, port),
+            None => SystemResolver.resolve(host, port),
+        };
+        match self.dns_cache {
+            Some(ref cache) => cache.get_or_resolve(host, port, lookup),
+            None => lookup(),
+        }
+    }
+
+    /// Digest of the TLS trust settings, `None` when they are the defaults
+    pub fn tls_fingerprint(&self) -> Option<u64> {
+        if self.root_certificates.is_empty() && !self.disable_built_in_roots && self.identity.is_none() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.root_certificates.hash(&mut hasher);
+        self.disable_built_in_roots.hash(&mut hasher);
+        self.identity.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+}
@@ -1,20 +1,42 @@
-pub const HTTP_VERSION: &'static str = "HTTP/1.0";
-pub const CL_METHODS: [&'static str; 2] = ["POST", "PUT"];
-pub const C_TYPE: [&'static str; 3] = [
+pub const HTTP_VERSION: &str = "HTTP/1.1";
+pub const C_TYPE: [&str; 3] = [
     "application/json",
     "application/x-www-form-urlencoded",
     "multipart/form-data",
 ];
-pub const SEP: &'static str = "\r\n";
+pub const SEP: &str = "\r\n";
 
 pub const DEF_PORT: u16 = 80;
-#[cfg(feature = "native-tls")]
+pub const DEF_SOCKS_PORT: u16 = 1080;
 pub const DEF_SSL_PORT: u16 = 443;
-pub const DEF_ACCEPT: &'static str = "*/*";
-pub const DEF_CONN: &'static str = "close";
+pub const DEF_ACCEPT: &str = "*/*";
+pub const DEF_CONN: &str = "close";
+pub const DEF_OCTET_STREAM: &str = "application/octet-stream";
+pub const DEF_KEEP_ALIVE: &str = "keep-alive";
+pub const DEF_CHUNKED: &str = "chunked";
+/// Largest chunk written for a request body of unknown length
+pub const CHUNK_SIZE: usize = 16 * 1024;
+pub const DEF_MAX_IDLE: usize = 64;
+pub const DEF_MAX_IDLE_PER_HOST: usize = 8;
+pub const DEF_IDLE_TIMEOUT: u64 = 90;
+pub const DEF_MAX_REDIRECTS: usize = 10;
+pub const DEF_MAX_HEADER_SIZE: usize = 64 * 1024;
+pub const DEF_MAX_HEADERS: usize = 100;
+pub const DEF_MAX_BODY_SIZE: u64 = 256 * 1024 * 1024;
+pub const DEF_MAX_DECODED_SIZE: u64 = 256 * 1024 * 1024;
+/// Bytes of a body quoted when it fails to parse as JSON
+pub const JSON_SNIPPET: usize = 200;
+/// Milliseconds between connection attempts, RFC 8305 recommends 250
+pub const DEF_HAPPY_EYEBALLS_DELAY: u64 = 250;
 
-pub const H_HOST: &'static str = "Host";
-pub const H_ACCPT: &'static str = "Accept";
-pub const H_CONN: &'static str = "Connection";
-pub const H_CTYPE: &'static str = "Content-Type";
-pub const H_CLEN: &'static str = "Content-Length";
+pub const H_HOST: &str = "Host";
+pub const H_ACCPT: &str = "Accept";
+pub const H_CONN: &str = "Connection";
+pub const H_CTYPE: &str = "Content-Type";
+pub const H_CLEN: &str = "Content-Length";
+pub const H_CENC: &str = "Content-Encoding";
+pub const H_AENC: &str = "Accept-Encoding";
+pub const H_TENC: &str = "Transfer-Encoding";
+pub const H_LOCATION: &str = "Location";
+pub const H_AUTH: &str = "Authorization";
+pub const H_PROXY_AUTH: &str = "Proxy-Authorization";
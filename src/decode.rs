@@ -0,0 +1,129 @@
+use std::io;
+use std::io::prelude::*;
+
+use crate::header::HeaderMap;
+use crate::consts::*;
+
+/// Boxed body reader, decoders stack on top of each other
+pub type Reader<'a> = Box<dyn Read + Send + 'a>;
+
+/// `Accept-Encoding` value listing the decoders compiled in, if any
+pub fn accept_encoding() -> Option<&'static str> {
+    const ENCODINGS: &[&str] = &["gzip", "deflate", "br", "zstd"];
+    static VALUE: ::std::sync::OnceLock<String> = ::std::sync::OnceLock::new();
+
+    let value = VALUE.get_or_init(|| {
+        let names: Vec<&str> = ENCODINGS.iter().cloned().filter(|e| supported(e)).collect();
+        names.join(", ")
+    });
+    match value.is_empty() {
+        true => None,
+        false => Some(value),
+    }
+}
+
+/// Content codings applied to a body, in the order they were applied
+pub fn content_encodings(header: &HeaderMap) -> Vec<String> {
+    header
+        .get_all(H_CENC)
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect()
+}
+
+/// Whether a decoder is compiled in for every coding
+pub fn can_decode(encodings: &[String]) -> bool {
+    encodings.iter().all(|coding| supported(coding))
+}
+
+fn supported(coding: &str) -> bool {
+    (cfg!(feature = "gzip") && (coding == "gzip" || coding == "x-gzip"))
+        || (cfg!(feature = "deflate") && coding == "deflate")
+        || (cfg!(feature = "brotli") && coding == "br")
+        || (cfg!(feature = "zstd") && coding == "zstd")
+}
+
+/// Undo every coding, last applied first
+///
+/// Check `can_decode` first, an unsupported coding is an error here.
+pub fn decoder<'a>(body: Reader<'a>, encodings: &[String]) -> io::Result<Reader<'a>> {
+    encodings.iter().rev().try_fold(body, |inner, coding| wrap(inner, coding))
+}
+
+fn wrap<'a>(inner: Reader<'a>, coding: &str) -> io::Result<Reader<'a>> {
+    match coding {
+        #[cfg(feature = "gzip")]
+        "gzip" | "x-gzip" => Ok(Box::new(flate2::read::MultiGzDecoder::new(inner))),
+        #[cfg(feature = "deflate")]
+        "deflate" => Ok(deflate(inner)),
+        #[cfg(feature = "brotli")]
+        "br" => Ok(Box::new(brotli_decompressor::Decompressor::new(inner, 8 * 1024))),
+        #[cfg(feature = "zstd")]
+        "zstd" => Ok(Box::new(zstd::stream::read::Decoder::new(inner)?)),
+        _ => {
+            drop(inner);
+            let msg = format!("no decoder for content coding {:?}", coding);
+            Err(io::Error::new(io::ErrorKind::Unsupported, msg))
+        }
+    }
+}
+
+/// `deflate` is meant to be zlib wrapped, some servers send raw deflate
+#[cfg(feature = "deflate")]
+fn deflate<'a>(inner: Reader<'a>) -> Reader<'a> {
+    Box::new(Deflate::Sniff(Some(io::BufReader::new(inner))))
+}
+
+/// Deflate decoder picking the framing on the first read, building it must
+/// not block on the body
+#[cfg(feature = "deflate")]
+enum Deflate<'a> {
+    Sniff(Option<io::BufReader<Reader<'a>>>),
+    Zlib(flate2::bufread::ZlibDecoder<io::BufReader<Reader<'a>>>),
+    Raw(flate2::bufread::DeflateDecoder<io::BufReader<Reader<'a>>>),
+}
+
+#[cfg(feature = "deflate")]
+impl<'a> Read for Deflate<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Deflate::Sniff(ref mut inner) = *self {
+            let zlib = match inner.as_mut().expect("not sniffed yet").fill_buf()? {
+                head if head.len() >= 2 => head[0] & 0x0f == 8 && (u16::from(head[0]) << 8 | u16::from(head[1])) % 31 == 0,
+                _ => true,
+            };
+            let inner = inner.take().expect("not sniffed yet");
+            *self = match zlib {
+                true => Deflate::Zlib(flate2::bufread::ZlibDecoder::new(inner)),
+                false => Deflate::Raw(flate2::bufread::DeflateDecoder::new(inner)),
+            };
+        }
+        match *self {
+            Deflate::Zlib(ref mut decoder) => decoder.read(buf),
+            Deflate::Raw(ref mut decoder) => decoder.read(buf),
+            Deflate::Sniff(_) => unreachable!("sniffed above"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "deflate"))]
+mod tests {
+    use super::decoder;
+    use std::io::{self, Read};
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))
+        }
+    }
+
+    #[test]
+    fn test_deflate_reads_lazily() {
+        let mut reader = decoder(Box::new(Failing), &["deflate".to_string()]).unwrap();
+        let err = reader.read(&mut [0; 8]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+}
@@ -0,0 +1,181 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Every address a connection was attempted to, and why it failed
+#[derive(Debug)]
+pub struct ConnectError {
+    attempts: Vec<(SocketAddr, io::Error)>,
+    timed_out: bool,
+}
+
+impl ConnectError {
+    #[cfg(feature = "async")]
+    pub(crate) fn new(attempts: Vec<(SocketAddr, io::Error)>, timed_out: bool) -> ConnectError {
+        ConnectError { attempts, timed_out }
+    }
+
+    /// Attempted addresses in the order they failed
+    pub fn attempts(&self) -> &[(SocketAddr, io::Error)] {
+        &self.attempts
+    }
+
+    /// Whether the connect timeout elapsed before any attempt succeeded
+    pub fn is_timeout(&self) -> bool {
+        self.timed_out
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.attempts.is_empty() {
+            return write!(f, "no address to connect to");
+        }
+        write!(f, "could not connect to any address (")?;
+        for (i, (addr, err)) in self.attempts.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", addr, err)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl error::Error for ConnectError {}
+
+/// Connect to the first address answering, racing attempts per RFC 8305
+///
+/// Addresses are tried alternating between IPv6 and IPv4, a new attempt
+/// starts every `delay` or as soon as the previous one failed, whichever
+/// comes first. The first connection established wins.
+pub fn happy_eyeballs(
+    addrs: Vec<SocketAddr>,
+    delay: Duration,
+    timeout: Option<Duration>,
+) -> Result<TcpStream, ConnectError> {
+    let mut queue = interleave(addrs).into_iter().peekable();
+    let mut failed = ConnectError {
+        attempts: Vec::new(),
+        timed_out: false,
+    };
+
+    // nothing to race, no thread needed
+    if queue.len() == 1 {
+        let addr = queue.next().expect("one address");
+        return attempt(addr, timeout).map_err(|err| {
+            failed.timed_out = err.kind() == io::ErrorKind::TimedOut;
+            failed.attempts.push((addr, err));
+            failed
+        });
+    }
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (tx, rx) = mpsc::channel();
+    let mut in_flight = 0;
+    let mut next_start = Instant::now();
+
+    loop {
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            failed.timed_out = true;
+            return Err(failed);
+        }
+
+        if queue.peek().is_some() && now >= next_start {
+            let addr = queue.next().expect("peeked");
+            let timeout = deadline.map(|deadline| deadline - now);
+            let tx = tx.clone();
+            // losers finish in the background, their sockets are dropped
+            thread::spawn(move || {
+                let _ = tx.send((addr, attempt(addr, timeout)));
+            });
+            in_flight += 1;
+            next_start = now + delay;
+        }
+        if in_flight == 0 {
+            return Err(failed);
+        }
+
+        let wake = match (queue.peek().map(|_| next_start), deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let received = match wake {
+            Some(wake) => rx.recv_timeout(wake.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok((_, Ok(stream))) => return Ok(stream),
+            Ok((addr, Err(err))) => {
+                in_flight -= 1;
+                failed.attempts.push((addr, err));
+                next_start = Instant::now();
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // `tx` is held here, senders cannot all be gone
+            Err(mpsc::RecvTimeoutError::Disconnected) => unreachable!(),
+        }
+    }
+}
+
+fn attempt(addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+}
+
+/// Alternate address families, starting with IPv6
+pub fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6());
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut sorted = Vec::new();
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return sorted,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{happy_eyeballs, interleave};
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_interleave() {
+        let addrs: Vec<SocketAddr> = vec!["1.1.1.1:80", "2.2.2.2:80", "[::1]:80", "[::2]:80", "[::3]:80"]
+            .into_iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let sorted: Vec<String> = interleave(addrs).iter().map(|a| a.to_string()).collect();
+
+        assert_eq!(sorted, vec!["[::1]:80", "1.1.1.1:80", "[::2]:80", "2.2.2.2:80", "[::3]:80"]);
+    }
+
+    #[test]
+    fn test_falls_back_past_refused_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let good = listener.local_addr().unwrap();
+        // nothing listens on the port of a dropped listener
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let start = Instant::now();
+        let stream = happy_eyeballs(vec![refused, good], Duration::from_secs(5), None).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), good);
+        // the refusal starts the next attempt without waiting for the delay
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let err = happy_eyeballs(vec![refused, refused], Duration::from_millis(10), None).unwrap_err();
+        assert_eq!(err.attempts().len(), 2);
+        assert!(!err.is_timeout());
+    }
+}
@@ -8,21 +8,91 @@ use std::io;
 #[cfg(feature = "native-tls")]
 use std::net::TcpStream;
 use std::num::ParseIntError;
-use url::ParseError;
+use std::time::Instant;
+use url::{ParseError, Url};
+
+use crate::consts::JSON_SNIPPET;
+use crate::dial::ConnectError;
+use crate::limit::Limit;
 #[cfg(feature = "native-tls")]
 use native_tls::HandshakeError;
 
+/// JSON that could not be written or read, with the start of the body
+/// that failed to parse
 #[derive(Debug)]
+pub struct JsonError {
+    inner: serde_json::Error,
+    snippet: Option<String>,
+}
+
+impl JsonError {
+    /// Error for a body that is not the expected JSON
+    pub(crate) fn parse(err: serde_json::Error, body: &[u8]) -> JsonError {
+        let end = body.len().min(JSON_SNIPPET);
+        JsonError {
+            inner: err,
+            snippet: Some(String::from_utf8_lossy(&body[..end]).into_owned()),
+        }
+    }
+
+    /// Start of the body that failed to parse, if it came from a response
+    pub fn snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+
+    pub fn inner(&self) -> &serde_json::Error {
+        &self.inner
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.snippet {
+            Some(ref snippet) => write!(f, "{} in body {:?}", self.inner, snippet),
+            None => write!(f, "{}", self.inner),
+        }
+    }
+}
+
+impl error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+/// Phase of a request that ran out of time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timeout {
+    Connect,
+    Read,
+    Write,
+    /// The overall deadline for the request elapsed
+    Total,
+}
+
+#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum HttpError {
     Parse(ParseError),
     IO(io::Error),
-    Json(serde_json::Error),
+    Json(JsonError),
     #[cfg(feature = "native-tls")]
     TLS(native_tls::Error),
     #[cfg(feature = "native-tls")]
     SSL(HandshakeError<TcpStream>),
+    #[cfg(feature = "rustls")]
+    Rustls(rustls::Error),
     ParseInt(ParseIntError),
     MissingFeature(String),
+    Timeout(Timeout),
+    TooManyRedirects(usize),
+    RedirectLoop(Url),
+    InvalidHeader(String),
+    InvalidMethod(String),
+    Proxy(String),
+    Connect(ConnectError),
+    /// The response went past one of the configured `Limits`
+    TooLarge(Limit),
 }
 
 impl From<ParseError> for HttpError {
@@ -33,13 +103,16 @@ impl From<ParseError> for HttpError {
 
 impl From<io::Error> for HttpError {
     fn from(err: io::Error) -> HttpError {
-        HttpError::IO(err)
+        match Limit::from_io(&err) {
+            Some(limit) => HttpError::TooLarge(limit),
+            None => HttpError::IO(err),
+        }
     }
 }
 
 impl From<serde_json::Error> for HttpError {
     fn from(err: serde_json::Error) -> HttpError {
-        HttpError::Json(err)
+        HttpError::Json(JsonError { inner: err, snippet: None })
     }
 }
 
@@ -57,6 +130,13 @@ impl From<HandshakeError<TcpStream>> for HttpError {
     }
 }
 
+#[cfg(feature = "rustls")]
+impl From<rustls::Error> for HttpError {
+    fn from(err: rustls::Error) -> HttpError {
+        HttpError::Rustls(err)
+    }
+}
+
 impl From<ParseIntError> for HttpError {
     fn from(err: ParseIntError) -> HttpError {
         HttpError::ParseInt(err)
@@ -68,13 +148,23 @@ impl fmt::Display for HttpError {
         match *self {
             HttpError::Parse(ref err) => write!(f, "Parse error: {}", err),
             HttpError::IO(ref err) => write!(f, "Parse error: {}", err),
-            HttpError::Json(ref err) => write!(f, "Parse error: {}", err),
+            HttpError::Json(ref err) => write!(f, "JSON error: {}", err),
             #[cfg(feature = "native-tls")]
             HttpError::TLS(ref err) => write!(f, "Parse error: {}", err),
             #[cfg(feature = "native-tls")]
             HttpError::SSL(ref err) => write!(f, "Parse error: {}", err),
+            #[cfg(feature = "rustls")]
+            HttpError::Rustls(ref err) => write!(f, "TLS error: {}", err),
             HttpError::ParseInt(ref err) => write!(f, "Parse error: {}", err),
             HttpError::MissingFeature(ref err) => write!(f, "Missing feature: {}", err),
+            HttpError::Timeout(ref phase) => write!(f, "Timeout: {}", phase),
+            HttpError::TooManyRedirects(max) => write!(f, "Redirect error: more than {} redirects", max),
+            HttpError::RedirectLoop(ref url) => write!(f, "Redirect error: loop at {}", url),
+            HttpError::InvalidHeader(ref err) => write!(f, "Invalid header: {}", err),
+            HttpError::InvalidMethod(ref method) => write!(f, "Invalid method: {:?}", method),
+            HttpError::Proxy(ref err) => write!(f, "Proxy error: {}", err),
+            HttpError::Connect(ref err) => write!(f, "Connect error: {}", err),
+            HttpError::TooLarge(ref limit) => write!(f, "Limit exceeded: {}", limit),
         }
     }
 }
@@ -89,8 +179,42 @@ impl error::Error for HttpError {
             HttpError::TLS(ref err) => Some(err),
             #[cfg(feature = "native-tls")]
             HttpError::SSL(ref err) => Some(err),
+            #[cfg(feature = "rustls")]
+            HttpError::Rustls(ref err) => Some(err),
             HttpError::ParseInt(ref err) => Some(err),
             HttpError::MissingFeature(ref _err) => None,
+            HttpError::Timeout(_) => None,
+            HttpError::TooManyRedirects(_) => None,
+            HttpError::RedirectLoop(_) => None,
+            HttpError::InvalidHeader(_) => None,
+            HttpError::InvalidMethod(_) => None,
+            HttpError::Proxy(_) => None,
+            HttpError::Connect(ref err) => Some(err),
+            HttpError::TooLarge(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Timeout::Connect => write!(f, "connect timed out"),
+            Timeout::Read => write!(f, "read timed out"),
+            Timeout::Write => write!(f, "write timed out"),
+            Timeout::Total => write!(f, "request deadline elapsed"),
+        }
+    }
+}
+
+impl HttpError {
+    /// Turn an IO error from the given phase into `Timeout` if it was one
+    pub(crate) fn timed_out(err: io::Error, phase: Timeout, deadline: Option<Instant>) -> HttpError {
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                let elapsed = deadline.is_some_and(|d| Instant::now() >= d);
+                HttpError::Timeout(if elapsed { Timeout::Total } else { phase })
+            }
+            _ => err.into(),
         }
     }
 }
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::ops::Index;
+
+use crate::err::HttpError;
+
+/// Ordered, case-insensitive collection of header fields
+///
+/// A name can carry several values (e.g. `Set-Cookie`), they are kept in
+/// the order they were added or received.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// let mut header = knock::HeaderMap::new();
+/// header.insert("Content-Type", "application/json").unwrap();
+/// header.append("Accept", "text/html").unwrap();
+/// header.append("accept", "application/json").unwrap();
+///
+/// assert_eq!(header.get("content-type"), Some("application/json"));
+/// assert_eq!(header.get_all("ACCEPT"), vec!["text/html", "application/json"]);
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap {
+            entries: Vec::new(),
+        }
+    }
+
+    /// First value of the header
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|&(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of the header in order
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|&(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Set the header to a single value, replacing any previous values
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<(), HttpError> {
+        validate(name, value)?;
+        self.set(name, value);
+        Ok(())
+    }
+
+    /// Add a value to the header, keeping previous values
+    pub fn append(&mut self, name: &str, value: &str) -> Result<(), HttpError> {
+        validate(name, value)?;
+        self.push(name, value);
+        Ok(())
+    }
+
+    /// Remove the header, returning its first value
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let value = self.get(name).map(|v| v.to_string());
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        value
+    }
+
+    /// Iterate over (name, value) pairs in order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Check every field, for maps built without going through `insert`/`append`
+    pub fn validate(&self) -> Result<(), HttpError> {
+        for (k, v) in &self.entries {
+            validate(k, v)?;
+        }
+        Ok(())
+    }
+
+    /// Add a received field as-is
+    pub(crate) fn push(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// Replace a field without validation
+    pub(crate) fn set(&mut self, name: &str, value: &str) {
+        let mut seen = false;
+        self.entries.retain(|(k, _)| {
+            let same = k.eq_ignore_ascii_case(name);
+            let keep = !(same && seen);
+            seen |= same;
+            keep
+        });
+
+        match self.entries.iter_mut().find(|entry| entry.0.eq_ignore_ascii_case(name)) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => self.push(name, value),
+        }
+    }
+}
+
+/// Header names are tokens, values may not contain CR, LF or other control characters
+fn validate(name: &str, value: &str) -> Result<(), HttpError> {
+    if name.is_empty() || !name.bytes().all(is_tchar) {
+        return Err(HttpError::InvalidHeader(format!("invalid header name {:?}", name)));
+    }
+    if value.bytes().any(|b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+        return Err(HttpError::InvalidHeader(format!("invalid value for header {}", name)));
+    }
+    Ok(())
+}
+
+/// RFC 7230 token character
+pub fn is_tchar(b: u8) -> bool {
+    match b {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`'
+        | b'|' | b'~' => true,
+        _ => b.is_ascii_alphanumeric(),
+    }
+}
+
+pub struct Iter<'a> {
+    inner: ::std::slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.next().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl Index<&str> for HeaderMap {
+    type Output = str;
+
+    fn index(&self, name: &str) -> &str {
+        self.get(name).expect("no such header")
+    }
+}
+
+/// Fields of the map are checked when the request is sent
+impl From<HashMap<String, String>> for HeaderMap {
+    fn from(map: HashMap<String, String>) -> HeaderMap {
+        let mut header = HeaderMap::new();
+        for (k, v) in map {
+            header.push(&k, &v);
+        }
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderMap;
+
+    #[test]
+    fn test_insert_replaces_all_values() {
+        let mut header = HeaderMap::new();
+        header.append("Set-Cookie", "a=1").unwrap();
+        header.append("X-Other", "x").unwrap();
+        header.append("set-cookie", "b=2").unwrap();
+        header.insert("SET-COOKIE", "c=3").unwrap();
+
+        assert_eq!(header.get_all("Set-Cookie"), vec!["c=3"]);
+        let names: Vec<&str> = header.iter().map(|(k, _)| k).collect();
+        assert_eq!(names, vec!["Set-Cookie", "X-Other"]);
+    }
+
+    #[test]
+    fn test_rejects_invalid_fields() {
+        let mut header = HeaderMap::new();
+
+        assert!(header.insert("Bad Name", "x").is_err());
+        assert!(header.insert("X-Injected", "a\r\nEvil: 1").is_err());
+        assert!(header.is_empty());
+    }
+}
@@ -1,29 +1,93 @@
-#![cfg_attr(feature="clippy", feature(plugin))]
-#![cfg_attr(feature="clippy", plugin(clippy))]
-
 extern crate url;
 extern crate rand;
+extern crate serde;
 extern crate serde_json;
 #[cfg(feature = "native-tls")]
 extern crate native_tls;
+#[cfg(feature = "rustls")]
+extern crate rustls;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+extern crate flate2;
+#[cfg(feature = "brotli")]
+extern crate brotli_decompressor;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(all(feature = "rustls", not(feature = "rustls-native-roots")))]
+extern crate webpki_roots;
+#[cfg(feature = "rustls-native-roots")]
+extern crate rustls_native_certs;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(all(feature = "async-native-tls", not(feature = "rustls")))]
+extern crate tokio_native_tls;
+#[cfg(feature = "async-rustls")]
+extern crate tokio_rustls;
 
-use std::net::TcpStream;
 use std::collections::HashMap;
-use std::io::{Error, ErrorKind};
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind};
 use std::io::prelude::*;
 use std::path::Path;
-use std::fs::File;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
 use rand::{Rng, distributions::Alphanumeric};
-use url::{Url, ParseError};
-use consts::*;
-use err::HttpError;
-use response::*;
-#[cfg(feature = "native-tls")]
-use native_tls::TlsConnector;
+use serde::ser::{Error as _, Serialize};
+use url::{form_urlencoded, Url, ParseError};
+use crate::consts::*;
+use crate::response::*;
+#[cfg(feature = "async")]
+use crate::async_client::AsyncConn;
+use crate::config::Config;
+use crate::decode::accept_encoding;
+use crate::payload::{Payload, Segment, Upload};
+use crate::pool::{Pool, Release};
+use crate::redirect::*;
+use crate::stream::{remaining, Conn, Timed};
+use crate::transport::{authority, default_port, dial_host};
+
+#[cfg(feature = "async")]
+pub use crate::async_client::AsyncClient;
+pub use crate::client::Client;
+pub use crate::dial::ConnectError;
+pub use crate::err::{HttpError, JsonError, Timeout};
+pub use crate::header::HeaderMap;
+pub use crate::limit::{Limit, Limits};
+pub use crate::method::Method;
+use crate::header::is_tchar;
+pub use crate::redirect::Redirect;
+pub use crate::resolve::{Resolve, SystemResolver};
+pub use crate::mock::MockTransport;
+pub use crate::proxy::Proxy;
+pub use crate::tls::{Certificate, Identity};
+pub use crate::transport::{Connection, Target, TcpTransport, Transport};
+#[cfg(unix)]
+pub use crate::transport::UnixTransport;
 
 mod err;
 mod consts;
+#[cfg(feature = "async")]
+mod async_client;
+mod decode;
+mod dial;
+mod body;
+mod client;
+mod config;
+mod header;
+mod limit;
+mod method;
+mod mock;
+mod payload;
+mod pool;
+mod proxy;
+mod redirect;
+mod resolve;
+mod stream;
+mod tls;
+mod transport;
 pub mod response;
 
 /// HTTP struct
@@ -38,21 +102,40 @@ pub struct HTTP {
     pub response: Response,
     pub url: url::Url,
 
-    pub method: String,
+    pub method: Method,
     pub body: HashMap<String, Data>,
-    pub header: HashMap<String, String>,
-    body_str: String,
-
-    danger_accept_invalid_certs: bool,
+    pub header: HeaderMap,
+    body_raw: Vec<u8>,
+    /// Content-Type implied by how `body_raw` was set
+    body_type: Option<&'static str>,
+    /// Why the value given to `json` could not be serialized
+    json_error: Option<String>,
+    upload: Option<Upload>,
 
     host: String,
     boundary: String,
-    response_str: String,
+    pool: Option<Pool>,
+    #[cfg(feature = "async")]
+    async_pool: Option<Pool<AsyncConn>>,
+    config: Config,
+    /// Proxies set on this request, ahead of those of the client
+    request_proxies: usize,
+    deadline: Option<Instant>,
+}
+
+/// Request as set up by the caller, restored once a send is done
+struct Rewind {
+    url: Url,
+    method: Method,
+    header: HeaderMap,
+    /// Body dropped when a redirect switched to GET
+    body: Option<(HashMap<String, Data>, Vec<u8>)>,
 }
 
 pub enum Data {
     File(String),
     String(String),
+    Bytes(Vec<u8>),
 }
 
 impl HTTP {
@@ -70,8 +153,11 @@ impl HTTP {
     pub fn new(url: &str) -> Result<HTTP, HttpError> {
         let response = Response {
             status: 0,
-            header: HashMap::new(),
-            body: String::new(),
+            header: HeaderMap::new(),
+            body: Vec::new(),
+            trailer: HeaderMap::new(),
+            redirects: Vec::new(),
+            decoded: false,
         };
         let url = Url::parse(url)?;
         let host_url = match url.host_str() {
@@ -80,19 +166,25 @@ impl HTTP {
         };
 
         Ok(HTTP {
-            response: response,
-            url: url,
+            response,
+            url,
 
-            danger_accept_invalid_certs: false,
-
-            method: String::new(),
+            method: Method::Extension(String::new()),
             body: HashMap::new(),
-            header: HashMap::new(),
-            body_str: String::new(),
+            header: HeaderMap::new(),
+            body_raw: Vec::new(),
+            body_type: None,
+            json_error: None,
+            upload: None,
 
             host: host_url,
             boundary: String::new(),
-            response_str: String::new(),
+            pool: None,
+            #[cfg(feature = "async")]
+            async_pool: None,
+            config: Config::default(),
+            request_proxies: 0,
+            deadline: None,
         })
     }
 
@@ -106,7 +198,7 @@ impl HTTP {
     /// ```
     ///
     pub fn get(&mut self) -> &mut Self {
-        self.method = "GET".to_string();
+        self.method = Method::Get;
         self
     }
 
@@ -120,7 +212,7 @@ impl HTTP {
     /// ```
     ///
     pub fn post(&mut self) -> &mut Self {
-        self.method = "POST".to_string();
+        self.method = Method::Post;
         self
     }
 
@@ -134,7 +226,7 @@ impl HTTP {
     /// ```
     ///
     pub fn put(&mut self) -> &mut Self {
-        self.method = "PUT".to_string();
+        self.method = Method::Put;
         self
     }
 
@@ -148,7 +240,77 @@ impl HTTP {
     /// ```
     ///
     pub fn delete(&mut self) -> &mut Self {
-        self.method = "DELETE".to_string();
+        self.method = Method::Delete;
+        self
+    }
+
+    /// PATCH request, e.g. for a partial update
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.patch().send();
+    /// ```
+    ///
+    pub fn patch(&mut self) -> &mut Self {
+        self.method = Method::Patch;
+        self
+    }
+
+    /// HEAD request, the response has headers only
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.head().send();
+    /// ```
+    ///
+    pub fn head(&mut self) -> &mut Self {
+        self.method = Method::Head;
+        self
+    }
+
+    /// OPTIONS request
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.options().send();
+    /// ```
+    ///
+    pub fn options(&mut self) -> &mut Self {
+        self.method = Method::Options;
+        self
+    }
+
+    /// TRACE request
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.trace().send();
+    /// ```
+    ///
+    pub fn trace(&mut self) -> &mut Self {
+        self.method = Method::Trace;
+        self
+    }
+
+    /// CONNECT request, asking a proxy for a tunnel to the URL's host and port
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.connect().send();
+    /// ```
+    ///
+    pub fn connect(&mut self) -> &mut Self {
+        self.method = Method::Connect;
         self
     }
 
@@ -162,7 +324,236 @@ impl HTTP {
     /// ```
     ///
     pub fn danger_accept_invalid_certs(&mut self, danger_accept_invalid_certs: bool) -> &mut Self {
-        self.danger_accept_invalid_certs = danger_accept_invalid_certs;
+        self.config.danger_accept_invalid_certs = danger_accept_invalid_certs;
+        self
+    }
+
+    /// Trust an additional root certificate for this request
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use knock::Certificate;
+    ///
+    /// let ca = Certificate::from_der(&[0x30, 0x82]);
+    /// let mut http = knock::HTTP::new("https://internal.example.com/").unwrap();
+    /// http.add_root_certificate(ca).get().send();
+    /// ```
+    ///
+    pub fn add_root_certificate(&mut self, cert: Certificate) -> &mut Self {
+        self.config.root_certificates.push(cert);
+        self
+    }
+
+    /// Trust only the certificates added with `add_root_certificate`
+    pub fn disable_built_in_roots(&mut self, disable: bool) -> &mut Self {
+        self.config.disable_built_in_roots = disable;
+        self
+    }
+
+    /// Present a client certificate during the TLS handshake
+    pub fn identity(&mut self, identity: Identity) -> &mut Self {
+        self.config.identity = Some(identity);
+        self
+    }
+
+    /// Route this request through a proxy
+    ///
+    /// Proxies set on the request are tried before those of the client, in
+    /// the order they were added.
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use knock::Proxy;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.proxy(Proxy::all("http://proxy.example.com:3128").unwrap()).get().send();
+    /// ```
+    ///
+    pub fn proxy(&mut self, proxy: Proxy) -> &mut Self {
+        self.config.proxies.insert(self.request_proxies, proxy);
+        self.request_proxies += 1;
+        self
+    }
+
+    /// Connect directly, ignoring proxies set on the client or environment
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let client = knock::Client::from_env();
+    /// let mut http = client.http("http://localhost:8080/health").unwrap();
+    /// http.no_proxy().get().send();
+    /// ```
+    ///
+    pub fn no_proxy(&mut self) -> &mut Self {
+        self.config.proxies.clear();
+        self.request_proxies = 0;
+        self
+    }
+
+    /// Open connections through a custom transport instead of TCP
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mock = knock::MockTransport::new();
+    /// mock.push_response("HTTP/1.1 204 No Content\r\n\r\n");
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// let response = http.transport(mock).delete().send().unwrap();
+    /// assert_eq!(response.status, 204);
+    /// ```
+    ///
+    pub fn transport<T: Transport + 'static>(&mut self, transport: T) -> &mut Self {
+        self.config.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Send the request over the Unix domain socket at `path`
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("http://localhost/v1.41/containers/json").unwrap();
+    /// http.unix_socket("/var/run/docker.sock").get().send();
+    /// ```
+    ///
+    #[cfg(unix)]
+    pub fn unix_socket<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.config.unix_socket = Some(UnixTransport::new(path));
+        self
+    }
+
+    /// Connect to `addr` for `host:port` instead of resolving it, like
+    /// `curl --resolve`
+    ///
+    /// ```rust,no_run
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://api.example.com/health").unwrap();
+    /// http.resolve("api.example.com:443", "10.0.0.5:443".parse().unwrap()).get().send();
+    /// ```
+    ///
+    pub fn resolve(&mut self, host_port: &str, addr: SocketAddr) -> &mut Self {
+        let addrs = self.config.resolve_overrides.entry(host_port.to_lowercase()).or_default();
+        addrs.push(addr);
+        self
+    }
+
+    /// Resolve host names with a custom resolver
+    pub fn resolver<R: Resolve + 'static>(&mut self, resolver: R) -> &mut Self {
+        self.config.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Whether to ask for and decode compressed responses, on by default
+    /// when one of the `gzip`, `deflate`, `brotli` or `zstd` features is enabled
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/archive.tar.gz").unwrap();
+    /// http.decompress(false).get().send();
+    /// ```
+    ///
+    pub fn decompress(&mut self, enabled: bool) -> &mut Self {
+        self.config.disable_decompression = !enabled;
+        self
+    }
+
+    /// Head start given to a connection attempt before the next resolved
+    /// address is tried in parallel, 250ms by default
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.happy_eyeballs_delay(Duration::from_millis(100)).get().send();
+    /// ```
+    ///
+    pub fn happy_eyeballs_delay(&mut self, delay: Duration) -> &mut Self {
+        self.config.happy_eyeballs_delay = Some(delay);
+        self
+    }
+
+    /// Give up connecting to the server after `timeout`
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// http.connect_timeout(Duration::from_secs(5)).get().send();
+    /// ```
+    ///
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail when the server sends nothing for longer than `timeout`
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail when the server accepts no request data for longer than `timeout`
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Overall deadline for the request, from connecting to the end of the body
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+    /// match http.timeout(Duration::from_secs(30)).get().send() {
+    ///     Err(knock::HttpError::Timeout(phase)) => println!("{}", phase),
+    ///     _ => {}
+    /// };
+    /// ```
+    ///
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// How to handle redirect responses, by default up to 10 hops are followed
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/old").unwrap();
+    /// http.redirect(knock::Redirect::None).get().send();
+    /// ```
+    ///
+    pub fn redirect(&mut self, policy: Redirect) -> &mut Self {
+        self.config.redirect = policy;
+        self
+    }
+
+    /// Bounds on the response head and body, reading past them fails with
+    /// `HttpError::TooLarge`
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/huge").unwrap();
+    /// let limits = knock::Limits { max_body_size: Some(4096), ..Default::default() };
+    /// http.limits(limits).get().send();
+    /// ```
+    ///
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.config.limits = limits;
         self
     }
 
@@ -172,11 +563,11 @@ impl HTTP {
     /// extern crate knock;
     ///
     /// let mut http = knock::HTTP::new("https://example.com/api/data").unwrap();
-    /// http.request("OPTIONS").send();
+    /// http.request("PROPFIND").send();
     /// ```
-    ///
-    pub fn request(&mut self, method: &str) -> &mut Self {
-        self.method = method.to_string();
+    /// Takes a `Method` or its name
+    pub fn request<M: Into<Method>>(&mut self, method: M) -> &mut Self {
+        self.method = method.into();
         self
     }
 
@@ -210,11 +601,116 @@ impl HTTP {
     /// But you need to set Content-Type in header,
     /// default Content-Type
     pub fn body_as_str(&mut self, data: &str) -> &mut Self {
-        self.body_str = data.to_string();
+        self.body_raw = data.as_bytes().to_vec();
+        self.body_type = None;
+        self.json_error = None;
         self
     }
 
-    /// Send Body data as HashMap<String, Data>
+    /// Raw binary body, sent as-is
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/api/data").unwrap();
+    /// http.post().body_as_bytes(&[0x89, 0x50, 0x4e, 0x47]).send();
+    /// ```
+    /// Sent with the Content-Type set in header, if any
+    pub fn body_as_bytes(&mut self, data: &[u8]) -> &mut Self {
+        self.body_raw = data.to_vec();
+        self.body_type = None;
+        self.json_error = None;
+        self
+    }
+
+    /// Form body of ordered pairs, keys may repeat
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/search").unwrap();
+    /// http.post().form(&[("tag", "rust"), ("tag", "http"), ("q", "a&b c")]).send();
+    /// ```
+    /// Sent as `application/x-www-form-urlencoded` unless header sets another Content-Type
+    pub fn form<K: AsRef<str>, V: AsRef<str>>(&mut self, pairs: &[(K, V)]) -> &mut Self {
+        let form = form_urlencoded::Serializer::new(String::new()).extend_pairs(pairs).finish();
+        self.body_raw = form.into_bytes();
+        self.body_type = Some(C_TYPE[1]);
+        self.json_error = None;
+        self
+    }
+
+    /// Any serializable value as JSON body
+    ///
+    /// ```rust
+    /// extern crate knock;
+    /// #[macro_use]
+    /// extern crate serde_json;
+    ///
+    /// # fn main() {
+    /// let mut http = knock::HTTP::new("https://example.com/api/items").unwrap();
+    /// http.post().json(&json!({ "name": "knock", "tags": ["http", "rust"], "stars": 5 })).send();
+    /// # }
+    /// ```
+    /// Sent as `application/json` unless header sets another Content-Type,
+    /// a value that cannot be serialized fails the send
+    pub fn json<T: Serialize + ?Sized>(&mut self, value: &T) -> &mut Self {
+        self.body_type = Some(C_TYPE[0]);
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                self.body_raw = body;
+                self.json_error = None;
+            }
+            Err(err) => {
+                self.body_raw = Vec::new();
+                self.json_error = Some(err.to_string());
+            }
+        }
+        self
+    }
+
+    /// Append query parameters to the URL, percent-encoded
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/search?page=2").unwrap();
+    /// http.query(&[("q", "knock & http"), ("lang", "en")]).get().send();
+    ///
+    /// assert_eq!(http.url.query(), Some("page=2&q=knock+%26+http&lang=en"));
+    /// ```
+    ///
+    pub fn query<K: AsRef<str>, V: AsRef<str>>(&mut self, pairs: &[(K, V)]) -> &mut Self {
+        if !pairs.is_empty() {
+            self.url.query_pairs_mut().extend_pairs(pairs);
+        }
+        self
+    }
+
+    /// Body read from `reader` while the request is written
+    ///
+    /// With a known `len` it is announced in `Content-Length`, otherwise the
+    /// body is sent with chunked transfer-encoding. The reader is used up by
+    /// the first `send`.
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::fs::File;
+    ///
+    /// let file = File::open("Cargo.toml").unwrap();
+    /// let len = file.metadata().unwrap().len();
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/upload").unwrap();
+    /// http.put().body_reader(file, Some(len)).send();
+    /// ```
+    /// Sent with the Content-Type set in header, if any
+    pub fn body_reader<R: Read + Send + 'static>(&mut self, reader: R, len: Option<u64>) -> &mut Self {
+        self.upload = Some(Upload::new(Box::new(reader), len));
+        self
+    }
+
+    /// Send Body data as HashMap<String, Data> with custom headers
     ///
     /// ```rust
     /// extern crate knock;
@@ -223,16 +719,16 @@ impl HTTP {
     ///
     /// let mut http = knock::HTTP::new("https://example.com/api/data").unwrap();
     /// let mut body: HashMap<String, knock::Data> = HashMap::new();
-    /// let mut header: HashMap<String, String> = HashMap::new();
+    /// let mut header = knock::HeaderMap::new();
     ///
     /// body.insert("key".to_string(), knock::Data::String("value".to_string()));
-    /// header.insert("Content-Type".to_string(), "application/json".to_string());
+    /// header.insert("Content-Type", "application/json").unwrap();
     ///
     /// http.post().body(body).header(header).send();
     /// ```
-    ///
-    pub fn header(&mut self, data: HashMap<String, String>) -> &mut Self {
-        self.header = data;
+    /// A HashMap<String, String> is accepted as well
+    pub fn header<H: Into<HeaderMap>>(&mut self, data: H) -> &mut Self {
+        self.header = data.into();
         self
     }
 
@@ -255,149 +751,474 @@ impl HTTP {
     /// ```
     ///
     pub fn send(&mut self) -> Result<Response, HttpError> {
+        let response = self.exchange(true)?;
+        let deadline = self.deadline;
+        response
+            .into_response()
+            .map_err(|err| HttpError::timed_out(err, Timeout::Read, deadline))
+    }
+
+    /// Send the request and return as soon as the response head is read
+    ///
+    /// The body of the `StreamingResponse` implements `Read`, nothing is
+    /// buffered beyond what the connection reads ahead. The connection
+    /// returns to the pool once the body is read to the end.
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::io::Read;
+    ///
+    /// let mut http = knock::HTTP::new("https://example.com/download").unwrap();
+    /// if let Ok(mut response) = http.get().send_streaming() {
+    ///     let mut chunk = [0; 8192];
+    ///     let n = response.body.read(&mut chunk);
+    /// }
+    /// ```
+    ///
+    pub fn send_streaming(&mut self) -> Result<StreamingResponse, HttpError> {
+        self.exchange(false)
+    }
+
+    /// Send the request, following redirects, with the body read into memory
+    /// afterwards if `buffered`
+    fn exchange(&mut self, buffered: bool) -> Result<StreamingResponse, HttpError> {
+        let mut rewind = self.start();
+        let result = self.follow_redirects(&mut rewind, buffered);
+        self.rewind(rewind);
+        result
+    }
+
+    /// Prepare a new exchange, returning the request as set up by the caller
+    fn start(&mut self) -> Rewind {
         self.boundary = rand::thread_rng()
             .sample_iter(&Alphanumeric)
             .take(32)
             .map(char::from)
             .collect::<String>();
+        self.deadline = self.config.timeout.map(|t| Instant::now() + t);
 
-        let url = self.url.host_str().ok_or(ParseError::EmptyHost)?;
-        self.host = url.to_string();
-        let request = self.create_request()?;
-        let response;
-
-        if self.url.scheme() == "http" {
-            let port = match self.url.port() {
-                Some(p) => p,
-                None => DEF_PORT,
-            };
-            let addr = format!("{}:{}", url, port);
-            let mut stream = TcpStream::connect(addr)?;
-            stream.write_all(request.as_bytes())?;
-            stream.read_to_string(&mut self.response_str)?;
-        } else {
-            self.response_str = self.tls_transport(request, url)?;
+        Rewind {
+            url: self.url.clone(),
+            method: self.method.clone(),
+            header: self.header.clone(),
+            body: None,
         }
+    }
+
+    /// Following redirects rewrites the request, put it back afterwards
+    fn rewind(&mut self, rewind: Rewind) {
+        self.url = rewind.url;
+        self.method = rewind.method;
+        self.header = rewind.header;
+        if let Some((body, body_raw)) = rewind.body {
+            self.body = body;
+            self.body_raw = body_raw;
+        }
+    }
+
+    fn follow_redirects(&mut self, rewind: &mut Rewind, buffered: bool) -> Result<StreamingResponse, HttpError> {
+        let mut chain: Vec<Url> = Vec::new();
+        let streamed = self.upload.is_some();
+
+        loop {
+            let mut response = self.send_once(buffered)?;
 
-        response = self.response_str.clone();
-        let resp = Response::new(response).unwrap();
-        Ok(resp)
+            if !self.next_hop(response.status, &response.header, &mut chain, streamed, rewind)? {
+                response.redirects = chain;
+                return Ok(response);
+            }
+            // read the redirect body off so its connection can be reused
+            let _ = io::copy(&mut response.body, &mut io::sink());
+        }
     }
 
-    #[cfg(feature = "native-tls")]
-    fn tls_transport(&self, request: String, url: &str) -> Result<String, HttpError> {
-        let port = match self.url.port() {
-            Some(p) => p,
-            None => DEF_SSL_PORT,
+    /// Rewrite the request to follow a redirect response, if it should be
+    ///
+    /// The URL redirected from is appended to `chain`.
+    fn next_hop(
+        &mut self,
+        status: u32,
+        header: &HeaderMap,
+        chain: &mut Vec<Url>,
+        streamed: bool,
+        rewind: &mut Rewind,
+    ) -> Result<bool, HttpError> {
+        let location = match header.get(H_LOCATION) {
+            Some(location) if is_redirect(status) => location,
+            _ => return Ok(false),
         };
-        let addr = format!("{}:{}", url, port);
+        // a Location that is not a URL is left to the caller
+        let next = match self.url.join(location) {
+            Ok(next) => next,
+            Err(_) => return Ok(false),
+        };
+
+        // a streamed body is used up, only a redirect dropping it can be followed
+        if streamed && !switches_to_get(status, self.method.as_str()) {
+            return Ok(false);
+        }
+
+        let follow = match self.config.redirect {
+            Redirect::None => false,
+            Redirect::Limited(max) => {
+                if chain.len() >= max {
+                    return Err(HttpError::TooManyRedirects(max));
+                }
+                true
+            }
+            Redirect::Custom(ref policy) => policy(&next, chain),
+        };
+        if !follow {
+            return Ok(false);
+        }
+
+        if next == self.url || chain.contains(&next) {
+            return Err(HttpError::RedirectLoop(next));
+        }
+
+        if switches_to_get(status, self.method.as_str()) {
+            self.method = Method::Get;
+            if rewind.body.is_none() {
+                rewind.body = Some((mem::take(&mut self.body), mem::take(&mut self.body_raw)));
+            }
+            self.header.remove(H_CTYPE);
+            self.header.remove(H_CLEN);
+        }
+        // credentials are meant for the origin they were given to
+        if !same_origin(&self.url, &next) {
+            self.header.remove(H_AUTH);
+        }
 
-        let connector = match self.danger_accept_invalid_certs {
-            true  => TlsConnector::builder().danger_accept_invalid_certs(true).build()?,
-            false => TlsConnector::builder().build()?,
+        chain.push(mem::replace(&mut self.url, next));
+        Ok(true)
+    }
+
+    fn send_once(&mut self, buffered: bool) -> Result<StreamingResponse, HttpError> {
+        let url = self.url.host_str().ok_or(ParseError::EmptyHost)?;
+        self.host = url.to_string();
+        let (request, mut payload) = self.create_request()?;
+
+        let (conn, head, release) = match self.pool.clone() {
+            Some(pool) => self.pooled_transport(pool, &request, &mut payload)?,
+            None => {
+                let mut conn = self.open_connection()?;
+                let head = self.round_trip(&mut conn, &request, &mut payload)?;
+                (conn, head, None)
+            }
         };
-        let stream = TcpStream::connect(addr)?;
-        let mut stream = connector.connect(&self.host, stream)?;
 
-        stream.write_all(request.as_bytes())?;
-        let mut buf = String::new();
-        stream.read_to_string(&mut buf)?;
-        Ok(buf)
+        let conn = Timed::new(conn, self.config.read_timeout, self.deadline);
+        let decompress = !self.config.disable_decompression;
+        let limits = &self.config.limits;
+        StreamingResponse::new(head, conn, release, decompress, buffered, limits)
+    }
+
+    /// Send request over a pooled keep-alive connection
+    ///
+    /// A reused connection may have been closed by the server while idle,
+    /// in that case the request is retried once on a fresh connection unless
+    /// its body was streamed. The connection goes back to the pool once the
+    /// response body has been read.
+    fn pooled_transport(
+        &self,
+        pool: Pool,
+        request: &[u8],
+        payload: &mut Payload,
+    ) -> Result<(Conn, MessageHead, Option<Release>), HttpError> {
+        let key = self.pool_key();
+
+        if let Some(mut conn) = pool.checkout(&key) {
+            match self.round_trip(&mut conn, request, payload) {
+                Ok(head) => return Ok((conn, head, Some((pool, key)))),
+                Err(HttpError::IO(ref err)) if is_stale(err) && payload.is_replayable() => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut conn = self.open_connection()?;
+        let head = self.round_trip(&mut conn, request, payload)?;
+        Ok((conn, head, Some((pool, key))))
+    }
+
+    /// Write the request head and body, then read the head of the response
+    fn round_trip(
+        &self,
+        conn: &mut Conn,
+        request: &[u8],
+        payload: &mut Payload,
+    ) -> Result<MessageHead, HttpError> {
+        let deadline = self.deadline;
+        let write_err = |err| HttpError::timed_out(err, Timeout::Write, deadline);
+        let read_err = |err| HttpError::timed_out(err, Timeout::Read, deadline);
+
+        let write_timeout = remaining(self.config.write_timeout, deadline).map_err(write_err)?;
+        conn.get_ref().set_write_timeout(write_timeout)?;
+        {
+            let mut out = BufWriter::new(conn.get_mut());
+            out.write_all(request).map_err(write_err)?;
+            payload.write_to(&mut out).map_err(write_err)?;
+            out.flush().map_err(write_err)?;
+        }
+
+        let mut conn = Timed::new(conn, self.config.read_timeout, deadline);
+        // nothing at all coming back means the peer already closed the connection
+        if conn.fill_buf().map_err(read_err)?.is_empty() {
+            let err = Error::new(ErrorKind::ConnectionAborted, "connection closed before response");
+            return Err(err.into());
+        }
+        read_message_head(&mut conn, &self.method, &self.config.limits).map_err(read_err)
+    }
+
+    /// Whether the connection goes back to a pool afterwards
+    fn pool_key(&self) -> String {
+        let port = self.url.port_or_known_default().unwrap_or(DEF_PORT);
+        let mut key = format!("{}://{}:{}", self.url.scheme(), self.host, port);
+        // never hand a connection that skipped certificate checks to a strict request
+        if self.config.danger_accept_invalid_certs {
+            key += "#insecure";
+        }
+        // nor one authenticated with other roots or client certificate
+        if let Some(fingerprint) = self.config.tls_fingerprint() {
+            key += &format!("#tls{:x}", fingerprint);
+        }
+        // nor one to another socket or through another proxy
+        #[cfg(unix)]
+        {
+            if let Some(ref unix) = self.config.unix_socket {
+                key += &format!("#unix={}", unix.path().display());
+            }
+        }
+        if let Some(proxy) = self.config.proxy_for(&self.url) {
+            key += &format!("#proxy={}", proxy.url());
+        }
+        // nor one dialled at an overridden address
+        if let Some(addrs) = self.config.resolve_overrides.get(&authority(&self.host, port).to_lowercase()) {
+            key += &format!("#addr={:?}", addrs);
+        }
+        // nor one opened by a different transport
+        if let Some(ref transport) = self.config.transport {
+            key += &format!("#{:p}", Arc::as_ptr(transport) as *const ());
+        }
+        key
     }
 
-    #[cfg(not(feature = "native-tls"))]
-    fn tls_transport(&self, _request: String, _url: &str) -> Result<String, HttpError> {
-        Err(HttpError::MissingFeature(
-            "Lib not compiled with feature native-tls active".into(),
-        ))
+    fn open_connection(&self) -> Result<Conn, HttpError> {
+        let scheme = self.url.scheme();
+        let host = dial_host(&self.url).ok_or(ParseError::EmptyHost)?;
+        let target = Target {
+            scheme,
+            host: &host,
+            port: self.url.port().unwrap_or_else(|| default_port(scheme)),
+            proxy: self.config.proxy_for(&self.url),
+            config: &self.config,
+            deadline: self.deadline,
+        };
+
+        let conn = match self.config.transport {
+            Some(ref transport) => transport.connect(&target)?,
+            #[cfg(unix)]
+            None if self.config.unix_socket.is_some() => {
+                let unix = self.config.unix_socket.as_ref().expect("checked above");
+                unix.connect(&target)?
+            }
+            None => TcpTransport.connect(&target)?,
+        };
+        Ok(BufReader::new(conn))
     }
-    /// Create Reqeust String
+
+    /// Create Reqeust head and the body to write after it
     ///
     /// Params: &mut self (HTTP)
     ///
-    /// Response: Result<String, HttpError>
+    /// Response: Result<(Vec<u8>, Payload), HttpError>
     ///
-    fn create_request(&self) -> Result<String, HttpError> {
-        let (mut header, c_type) = organize_header(&self.header, &self.host);
+    fn create_request(&mut self) -> Result<(Vec<u8>, Payload), HttpError> {
+        let keep_alive = self.pool.is_some();
+        self.build_request(keep_alive)
+    }
+
+    /// Request head and body, asking to keep the connection open if it
+    /// goes back to a pool afterwards
+    fn build_request(&mut self, keep_alive: bool) -> Result<(Vec<u8>, Payload), HttpError> {
+        let conn = match keep_alive {
+            true => DEF_KEEP_ALIVE,
+            false => DEF_CONN,
+        };
+        // nothing user supplied may end up breaking the request framing
+        let method = self.method.as_str();
+        if method.is_empty() || !method.bytes().all(is_tchar) {
+            return Err(HttpError::InvalidMethod(method.to_string()));
+        }
+        self.header.validate()?;
+        if let Some(ref msg) = self.json_error {
+            return Err(serde_json::Error::custom(msg).into());
+        }
+        // the port is part of Host unless it is the scheme default, which Url omits
+        let host = match self.url.port() {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
+        };
+        let (mut header, c_type) = organize_header(&self.header, &host, conn);
+        // form, json and body map contents bring a type unless one was given,
+        // raw and streamed bodies are sent with the caller's type or none
+        let body_type = match self.upload {
+            None if !self.body_raw.is_empty() => self.body_type,
+            None if !self.body.is_empty() => Some(C_TYPE[0]),
+            _ => None,
+        };
+        if let Some(body_type) = body_type {
+            if !header.contains_key(H_CTYPE) {
+                header.set(H_CTYPE, body_type);
+            }
+        }
+        if !self.config.disable_decompression && !header.contains_key(H_AENC) {
+            if let Some(encodings) = accept_encoding() {
+                header.set(H_AENC, encodings);
+            }
+        }
 
-        let body = if self.body_str.is_empty() {
-            create_body(&c_type, &self.body, header.clone(), &self.boundary)?
+        // without a body nothing may follow the head, or a kept-alive
+        // connection would read the leftovers as the next response
+        let body = if let Some(upload) = self.upload.take() {
+            Payload::Stream(Some(upload))
+        } else if !self.body_raw.is_empty() {
+            Payload::Bytes(self.body_raw.clone())
+        } else if !self.body.is_empty() {
+            create_body(&c_type, &self.body, &mut header, &self.boundary)?
         } else {
-            self.body_str.clone()
+            Payload::Bytes(Vec::new())
         };
 
-        {
-            let cl_methods = CL_METHODS;
-            let method_exist = cl_methods.iter().find(|&&x| x == self.method);
-            if method_exist.is_some() {
-                header.insert(H_CLEN.to_string(), body.len().to_string());
-            }
+        // any body is announced, an empty one where the method expects content
+        // or when it was streamed
+        match body.len() {
+            Some(0) if !self.method.expects_body() && body.is_replayable() => {}
+            Some(len) => header.set(H_CLEN, &len.to_string()),
+            None => header.set(H_TENC, DEF_CHUNKED),
         }
 
-        let path = match self.url.query() {
+        // Url percent-encodes path and query, they cannot carry CR, LF or spaces
+        let mut path = match self.url.query() {
             Some(q) => format!("{}?{}", self.url.path(), q),
             None => self.url.path().to_string(),
         };
+        // CONNECT names the host and port to open a tunnel to instead
+        if self.method == Method::Connect {
+            let port = self.url.port_or_known_default().unwrap_or(DEF_PORT);
+            path = format!("{}:{}", self.host, port);
+        } else if let Some(proxy) = self.config.proxy_for(&self.url).filter(|p| p.forwards(&self.url)) {
+            // a forwarding proxy needs the absolute-form to know where to go
+            path = format!("{}://{}{}", self.url.scheme(), host, path);
+            if let Some(auth) = proxy.authorization() {
+                header.set(H_PROXY_AUTH, &auth);
+            }
+        }
         let mut str = String::new();
         str += &format!("{} {} {}{}", self.method, path, HTTP_VERSION, SEP);
 
         for (key, val) in &header {
             str += &format!("{}: {}{}", key, val, SEP);
         }
+        str += SEP;
+
+        Ok((str.into_bytes(), body))
+    }
+}
 
-        Ok(format!("{}{}{}", str, SEP, body))
+/// Whether an IO error means a pooled connection was closed while idle
+fn is_stale(err: &Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+    )
+}
+
+/// Multipart names and filenames are quoted strings inside a part header
+fn validate_part_param(value: &str) -> Result<(), HttpError> {
+    if value.bytes().any(|b| b < 0x20 || b == 0x7f || b == b'"') {
+        return Err(HttpError::InvalidHeader(format!("invalid multipart parameter {:?}", value)));
     }
+    Ok(())
 }
 
 /// Create Body for request
 ///
-/// Params: `c_type`: &str, body: &`HashMap`<String, Data>, mut header: `HashMap`<String, String>, b: &str
+/// Params: `c_type`: &str, body: &`HashMap`<String, Data>, header: &mut `HeaderMap`, b: &str
 ///
-/// Response: Result<String, `HttpError`>
+/// Response: Result<Payload, `HttpError`>
 ///
 fn create_body(
     c_type: &str,
     body: &HashMap<String, Data>,
-    mut header: HashMap<String, String>,
+    header: &mut HeaderMap,
     b: &str,
-) -> Result<String, HttpError> {
-    let mut res = String::new();
+) -> Result<Payload, HttpError> {
+    let res;
 
     if c_type == C_TYPE[1] {
-        for (key, val) in body.iter() {
-            match *val {
-                Data::String(ref str) => res += &format!("{}={}", key, str),
-                Data::File(_) => continue,
-            }
-            if body.keys().last().unwrap() != key {
-                res += "&";
+        // sorted so that the same map always gives the same body
+        let mut keys: Vec<&String> = body.keys().collect();
+        keys.sort();
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        for key in keys {
+            if let Data::String(ref str) = body[key] {
+                form.append_pair(key, str);
             }
         }
+        res = form.finish();
     } else if c_type == C_TYPE[2] {
-        *header.get_mut(H_CTYPE).unwrap() = format!("{}; boundary={}", c_type, b);
+        header.set(H_CTYPE, &format!("{}; boundary={}", c_type, b));
+        let mut segments = Vec::new();
+        let mut res: Vec<u8> = Vec::new();
 
         for (key, val) in body.iter() {
-            res += &format!("--{}{}", b, SEP);
+            validate_part_param(key)?;
+            res.extend_from_slice(format!("--{}{}", b, SEP).as_bytes());
             match *val {
                 Data::File(ref str) => {
-                    res += &format!("Content-Disposition: form-data; name={};", key);
                     let file_name = Path::new(str).file_name().ok_or_else(|| {
                         Error::new(ErrorKind::InvalidData, "wrong file path")
                     })?;
-                    res += &format!(" filename={0}{1}{1}", file_name.to_str().unwrap(), SEP);
-                    let mut buffer = String::new();
-                    let mut file = File::open(str)?;
-                    file.read_to_string(&mut buffer)?;
-                    res += &format!("{}{}", buffer, SEP);
+                    let file_name = file_name.to_string_lossy();
+                    validate_part_param(&file_name)?;
+                    let part = format!(
+                        "Content-Disposition: form-data; name=\"{0}\"; filename=\"{1}\"{4}{2}: {3}{4}{4}",
+                        key,
+                        file_name,
+                        H_CTYPE,
+                        DEF_OCTET_STREAM,
+                        SEP
+                    );
+                    res.extend_from_slice(part.as_bytes());
+                    // file contents are copied from disk while sending
+                    segments.push(Segment::Bytes(mem::take(&mut res)));
+                    segments.push(Segment::file(PathBuf::from(str))?);
                 }
                 Data::String(ref str) => {
-                    res += &format!("Content-Disposition: form-data; name={0}{1}{1}", key, SEP);
-                    res += &format!("{}{}", str, SEP);
+                    res.extend_from_slice(
+                        format!("Content-Disposition: form-data; name=\"{0}\"{1}{1}", key, SEP).as_bytes(),
+                    );
+                    res.extend_from_slice(str.as_bytes());
+                }
+                Data::Bytes(ref bytes) => {
+                    let part = format!(
+                        "Content-Disposition: form-data; name=\"{0}\"{3}{1}: {2}{3}{3}",
+                        key,
+                        H_CTYPE,
+                        DEF_OCTET_STREAM,
+                        SEP
+                    );
+                    res.extend_from_slice(part.as_bytes());
+                    res.extend_from_slice(bytes);
                 }
             }
+            res.extend_from_slice(SEP.as_bytes());
         }
 
-        res += &format!("--{}--", b);
+        res.extend_from_slice(format!("--{}--", b).as_bytes());
+        segments.push(Segment::Bytes(res));
+        return Ok(Payload::Multipart(segments));
     } else {
         let mut tmp_map: HashMap<&str, &str> = HashMap::new();
         for (key, val) in body.iter() {
@@ -405,62 +1226,42 @@ fn create_body(
                 Data::String(ref str) => {
                     tmp_map.insert(key, str);
                 }
-                Data::File(_) => continue,
+                Data::File(_) | Data::Bytes(_) => continue,
             }
         }
 
         res = serde_json::to_string(&tmp_map)?;
     }
 
-    Ok(res)
+    Ok(Payload::Bytes(res.into_bytes()))
 }
 
 /// Update Header
 ///
-/// Params: mut header: `HashMap`<String, String>, host: String
+/// Params: header: &`HeaderMap`, host: &str, conn: &str
 ///
-/// Response: (Result<String, `HttpError`>, String)
+/// Response: (`HeaderMap`, String)
 ///
-fn organize_header(
-    header: &HashMap<String, String>,
-    host: &str,
-) -> (HashMap<String, String>, String) {
-    let mut data: HashMap<String, String> = HashMap::new();
+fn organize_header(header: &HeaderMap, host: &str, conn: &str) -> (HeaderMap, String) {
+    let mut data = HeaderMap::new();
     let mut c_type = String::new();
 
-    if !header.is_empty() {
-        for (key, val) in header {
-            if data.contains_key(val) {
-                let p_val = data[key].to_string(); // Always be Some(val)
-                let str = format!("{}; {}", p_val, val);
-                data.insert(key.to_string(), str);
-            } else {
-                data.insert(key.to_string(), val.to_string());
-            }
-        }
+    if !header.contains_key(H_HOST) {
+        data.set(H_HOST, host);
     }
-
-    if !data.contains_key(H_HOST) {
-        data.insert(H_HOST.to_string(), host.to_string());
+    for (key, val) in header {
+        data.push(key, val);
     }
+
     if !data.contains_key(H_ACCPT) {
-        data.insert(H_ACCPT.to_string(), DEF_ACCEPT.to_string());
+        data.set(H_ACCPT, DEF_ACCEPT);
     }
     if !data.contains_key(H_CONN) {
-        data.insert(H_CONN.to_string(), DEF_CONN.to_string());
+        data.set(H_CONN, conn);
     }
-    if data.contains_key(H_CTYPE) {
-        let c_types = C_TYPE;
-        c_type = data[H_CTYPE].to_string();
-        let c_type_exist = c_types.iter().find(|&&x| x == c_type);
-        match c_type_exist {
-            None => {
-                data.insert(H_CTYPE.to_string(), C_TYPE[0].to_string());
-            }
-            Some(_) => {}
-        }
-    } else {
-        data.insert(H_CTYPE.to_string(), C_TYPE[0].to_string());
+    // the media type decides how a body map is encoded, parameters aside
+    if let Some(val) = data.get(H_CTYPE) {
+        c_type = val.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
     }
 
     (data, c_type)
@@ -468,15 +1269,97 @@ fn organize_header(
 
 #[cfg(test)]
 mod tests {
-    use super::HTTP;
+    use std::collections::HashMap;
+
+    use super::{Data, HeaderMap, HttpError, Method, Payload, HTTP};
+
+    #[test]
+    fn test_invalid_method() {
+        let mut http = HTTP::new("http://moo.com/").unwrap();
+        assert!(http.request("GET / HTTP/1.1\r\nX:").create_request().is_err());
+        assert!(http.request("").create_request().is_err());
+        assert!(http.request("PROPFIND").create_request().is_ok());
+    }
+
+    #[test]
+    fn test_path_cannot_inject() {
+        let mut http = HTTP::new("http://moo.com/a b\r\nX-Evil: 1").unwrap();
+        let (request, _) = http.get().create_request().unwrap();
+        assert!(request.starts_with(b"GET /a%20bX-Evil:%201 HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_form_body_is_encoded() {
+        let mut body = HashMap::new();
+        body.insert("b".to_string(), Data::String("x=1&y=2".to_string()));
+        body.insert("a".to_string(), Data::String("hello world".to_string()));
+        let mut header = HeaderMap::new();
+        header.insert("Content-Type", "application/x-www-form-urlencoded").unwrap();
+        let mut http = HTTP::new("http://moo.com/").unwrap();
+        let (_, payload) = http.post().header(header).body(body).create_request().unwrap();
+        assert!(matches!(payload, Payload::Bytes(ref b) if b == b"a=hello+world&b=x%3D1%26y%3D2"));
+
+        let mut http = HTTP::new("http://moo.com/").unwrap();
+        let (request, payload) = http.post().form(&[("k", "1"), ("k", "2 3")]).create_request().unwrap();
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.contains("Content-Type: application/x-www-form-urlencoded\r\n"));
+        assert!(matches!(payload, Payload::Bytes(ref b) if b == b"k=1&k=2+3"));
+    }
+
+    #[test]
+    fn test_json_body() {
+        let mut value = HashMap::new();
+        value.insert("tags", vec![1, 2]);
+        let mut http = HTTP::new("http://moo.com/").unwrap();
+        let (request, payload) = http.post().json(&value).create_request().unwrap();
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.contains("Content-Type: application/json\r\n"));
+        assert!(request.contains("Content-Length: 14\r\n"));
+        assert!(matches!(payload, Payload::Bytes(ref b) if b == br#"{"tags":[1,2]}"#));
+
+        // map keys have to serialize as strings
+        let mut value = HashMap::new();
+        value.insert(vec![1], 1);
+        assert!(matches!(http.json(&value).create_request(), Err(HttpError::Json(_))));
+    }
+
+    #[test]
+    fn test_content_length_for_any_body() {
+        let request = |method: Method, body: &str| {
+            let mut http = HTTP::new("http://moo.com/").unwrap();
+            let (head, _) = http.request(method).body_as_str(body).create_request().unwrap();
+            String::from_utf8(head).unwrap()
+        };
+
+        assert!(request(Method::Patch, "{}").contains("\r\nContent-Length: 2\r\n"));
+        assert!(request(Method::Delete, "{}").contains("\r\nContent-Length: 2\r\n"));
+        assert!(request(Method::from("PROPFIND"), "<a/>").contains("\r\nContent-Length: 4\r\n"));
+        assert!(request(Method::Post, "").contains("\r\nContent-Length: 0\r\n"));
+        assert!(!request(Method::Get, "").contains("Content-Length"));
+        assert!(!request(Method::Head, "").contains("Content-Length"));
+    }
+
+    #[test]
+    fn test_connect_uses_authority_form() {
+        let mut http = HTTP::new("https://moo.com/ignored?path").unwrap();
+        let (request, _) = http.connect().create_request().unwrap();
+        assert!(request.starts_with(b"CONNECT moo.com:443 HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_query_builder() {
+        let mut http = HTTP::new("http://moo.com/?foo=bar").unwrap();
+        let (request, _) = http.query(&[("q", "a&b=c"), ("q", "ü")]).get().create_request().unwrap();
+        assert!(request.starts_with(b"GET /?foo=bar&q=a%26b%3Dc&q=%C3%BC HTTP/1.1\r\n"));
+    }
 
     #[test]
     fn test_query_params() {
         let mut http = HTTP::new("http://moo.com/?foo=bar").unwrap();
         let expected = "GET /?foo=bar".to_string();
         assert_eq!(
-            http.get().create_request().unwrap()[0..expected.len()],
-            expected
+            http.get().create_request().unwrap().0[0..expected.len()],
+            expected.as_bytes()[..]
         );
     }
 }
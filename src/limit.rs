@@ -0,0 +1,162 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use crate::consts::*;
+
+/// Bounds on what is read from a server, enforced while reading
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use knock::Limits;
+///
+/// let mut http = knock::HTTP::new("https://example.com/small.json").unwrap();
+/// http.limits(Limits {
+///     max_body_size: Some(1024 * 1024),
+///     ..Limits::default()
+/// });
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Bytes in the response head, status line included
+    pub max_header_size: usize,
+    /// Header fields in the response head
+    pub max_headers: usize,
+    /// Body bytes as sent on the wire, after removing chunked framing
+    ///
+    /// Unset, a buffered body is bounded by 256 MiB and a streamed one is not.
+    pub max_body_size: Option<u64>,
+    /// Body bytes after undoing `Content-Encoding`
+    ///
+    /// Unset, a buffered body is bounded by 256 MiB and a streamed one is not.
+    pub max_decoded_size: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_header_size: DEF_MAX_HEADER_SIZE,
+            max_headers: DEF_MAX_HEADERS,
+            max_body_size: None,
+            max_decoded_size: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Bound on the body, whether it is read into memory or streamed
+    pub(crate) fn body_size(&self, buffered: bool) -> Option<u64> {
+        match self.max_body_size {
+            Some(max) => Some(max),
+            None if buffered => Some(DEF_MAX_BODY_SIZE),
+            None => None,
+        }
+    }
+
+    /// Bound on the decoded body, whether it is read into memory or streamed
+    pub(crate) fn decoded_size(&self, buffered: bool) -> Option<u64> {
+        match self.max_decoded_size {
+            Some(max) => Some(max),
+            None if buffered => Some(DEF_MAX_DECODED_SIZE),
+            None => None,
+        }
+    }
+}
+
+/// Limit a response went past, with the configured maximum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    HeaderSize(usize),
+    HeaderCount(usize),
+    BodySize(u64),
+    DecodedSize(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::HeaderSize(max) => write!(f, "response head larger than {} bytes", max),
+            Limit::HeaderCount(max) => write!(f, "more than {} header fields", max),
+            Limit::BodySize(max) => write!(f, "response body larger than {} bytes", max),
+            Limit::DecodedSize(max) => write!(f, "decoded body larger than {} bytes", max),
+        }
+    }
+}
+
+impl error::Error for Limit {}
+
+impl From<Limit> for io::Error {
+    fn from(limit: Limit) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, limit)
+    }
+}
+
+impl Limit {
+    /// The limit carried by an IO error created from one
+    pub(crate) fn from_io(err: &io::Error) -> Option<Limit> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<Limit>()).copied()
+    }
+}
+
+/// Reader failing with `limit` once more than `max` bytes came through
+pub struct Limited<R> {
+    inner: R,
+    left: u64,
+    limit: Limit,
+}
+
+impl<R: Read> Limited<R> {
+    pub fn new(inner: R, max: u64, limit: Limit) -> Limited<R> {
+        Limited {
+            inner,
+            left: max,
+            limit,
+        }
+    }
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // one byte past the limit is enough to tell it was exceeded
+        let max = self.left.saturating_add(1).min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        if n as u64 > self.left {
+            return Err(self.limit.into());
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limit, Limited, Limits};
+    use crate::consts::{DEF_MAX_BODY_SIZE, DEF_MAX_DECODED_SIZE};
+    use std::io::Read;
+
+    #[test]
+    fn test_limited_reader() {
+        let mut buf = Vec::new();
+        Limited::new(&b"four"[..], 4, Limit::BodySize(4)).read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"four");
+
+        let err = Limited::new(&b"fives"[..], 4, Limit::BodySize(4)).read_to_end(&mut buf).unwrap_err();
+        assert_eq!(Limit::from_io(&err), Some(Limit::BodySize(4)));
+    }
+
+    #[test]
+    fn test_default_bounds_buffered_only() {
+        let limits = Limits::default();
+        assert_eq!(limits.body_size(true), Some(DEF_MAX_BODY_SIZE));
+        assert_eq!(limits.body_size(false), None);
+        assert_eq!(limits.decoded_size(true), Some(DEF_MAX_DECODED_SIZE));
+        assert_eq!(limits.decoded_size(false), None);
+
+        let limits = Limits { max_decoded_size: Some(64), ..Limits::default() };
+        assert_eq!(limits.decoded_size(true), Some(64));
+        assert_eq!(limits.decoded_size(false), Some(64));
+    }
+}
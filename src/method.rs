@@ -0,0 +1,104 @@
+use std::fmt;
+
+/// Request method, any other token is an extension method
+///
+/// Methods are case-sensitive, `"get"` is an extension and not `Get`.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use knock::Method;
+///
+/// assert_eq!(Method::from("PATCH"), Method::Patch);
+/// assert_eq!(Method::from("PROPFIND"), Method::Extension("PROPFIND".to_string()));
+///
+/// let mut http = knock::HTTP::new("https://example.com/dav/").unwrap();
+/// http.request(Method::from("PROPFIND")).send();
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    Extension(String),
+}
+
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(ref name) => name,
+        }
+    }
+
+    /// Whether requests carry content, announced even when empty
+    pub fn expects_body(&self) -> bool {
+        matches!(*self, Method::Post | Method::Put | Method::Patch)
+    }
+}
+
+impl<'a> From<&'a str> for Method {
+    fn from(name: &'a str) -> Method {
+        match name {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            _ => Method::Extension(name.to_string()),
+        }
+    }
+}
+
+impl From<String> for Method {
+    fn from(name: String) -> Method {
+        match Method::from(name.as_str()) {
+            Method::Extension(_) => Method::Extension(name),
+            method => method,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<String> for Method {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Method;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Method::from("DELETE"), Method::Delete);
+        assert_eq!(Method::from("delete"), Method::Extension("delete".to_string()));
+        assert_eq!(Method::from("MKCOL".to_string()).as_str(), "MKCOL");
+        assert!(Method::Patch == "PATCH".to_string());
+    }
+}
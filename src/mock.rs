@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::err::HttpError;
+use crate::transport::{Connection, Target, Transport};
+
+#[derive(Default)]
+struct Inner {
+    responses: VecDeque<Vec<u8>>,
+    requests: Vec<Vec<u8>>,
+    connects: Vec<String>,
+}
+
+/// In-memory transport answering with scripted responses
+///
+/// Every request written to it is recorded byte for byte. Responses are
+/// handed out in the order they were pushed, one per request; when none
+/// are left the connection behaves as if the server closed it.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// let mock = knock::MockTransport::new();
+/// mock.push_response("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+///
+/// let mut http = knock::HTTP::new("http://example.com/ping").unwrap();
+/// let response = http.transport(mock.clone()).get().send().unwrap();
+///
+/// assert_eq!(response.text(), "ok");
+/// assert!(mock.requests()[0].starts_with(b"GET /ping HTTP/1.1\r\n"));
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Queue a raw response message
+    pub fn push_response<B: AsRef<[u8]>>(&self, raw: B) -> &Self {
+        self.lock().responses.push_back(raw.as_ref().to_vec());
+        self
+    }
+
+    /// Raw bytes of every request sent so far
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        self.lock().requests.clone()
+    }
+
+    /// `host:port` of every connection opened so far
+    pub fn connects(&self) -> Vec<String> {
+        self.lock().connects.clone()
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Transport for MockTransport {
+    fn connect(&self, target: &Target) -> Result<Box<dyn Connection>, HttpError> {
+        self.lock().connects.push(target.authority());
+        Ok(Box::new(MockConnection {
+            mock: self.clone(),
+            written: Vec::new(),
+            response: io::Cursor::new(Vec::new()),
+        }))
+    }
+}
+
+struct MockConnection {
+    mock: MockTransport,
+    written: Vec<u8>,
+    response: io::Cursor<Vec<u8>>,
+}
+
+impl Read for MockConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // a request was written since the last response, record it and answer
+        if !self.written.is_empty() {
+            let mut inner = self.mock.lock();
+            let request = ::std::mem::take(&mut self.written);
+            inner.requests.push(request);
+            let next = inner.responses.pop_front().unwrap_or_default();
+            self.response = io::Cursor::new(next);
+        }
+        self.response.read(buf)
+    }
+}
+
+impl Write for MockConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for MockConnection {}
@@ -0,0 +1,217 @@
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+#[cfg(feature = "async")]
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::consts::*;
+
+/// Request body handed over as a reader, sent at most once
+pub struct Upload {
+    reader: Box<dyn Read + Send>,
+    len: Option<u64>,
+}
+
+impl Upload {
+    pub fn new(reader: Box<dyn Read + Send>, len: Option<u64>) -> Upload {
+        Upload { reader, len }
+    }
+
+    /// Length announced in `Content-Length`, chunked when unknown
+    pub fn len(&self) -> Option<u64> {
+        self.len
+    }
+}
+
+/// Piece of a multipart body
+pub enum Segment {
+    Bytes(Vec<u8>),
+    /// File copied from disk while the request is written
+    File(PathBuf, u64),
+}
+
+impl Segment {
+    pub fn file(path: PathBuf) -> io::Result<Segment> {
+        let len = fs::metadata(&path)?.len();
+        Ok(Segment::File(path, len))
+    }
+
+    fn len(&self) -> u64 {
+        match *self {
+            Segment::Bytes(ref bytes) => bytes.len() as u64,
+            Segment::File(_, len) => len,
+        }
+    }
+}
+
+/// Body written after the request head
+pub enum Payload {
+    Bytes(Vec<u8>),
+    Multipart(Vec<Segment>),
+    Stream(Option<Upload>),
+}
+
+impl Payload {
+    /// Body length if known up front, `None` means chunked
+    pub fn len(&self) -> Option<u64> {
+        match *self {
+            Payload::Bytes(ref bytes) => Some(bytes.len() as u64),
+            Payload::Multipart(ref segments) => Some(segments.iter().map(Segment::len).sum()),
+            Payload::Stream(ref upload) => upload.as_ref().and_then(Upload::len),
+        }
+    }
+
+    /// Whether the body can be written again, e.g. on a fresh connection
+    pub fn is_replayable(&self) -> bool {
+        !matches!(*self, Payload::Stream(_))
+    }
+
+    pub fn write_to<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        match *self {
+            Payload::Bytes(ref bytes) => out.write_all(bytes),
+            Payload::Multipart(ref segments) => {
+                for segment in segments {
+                    match *segment {
+                        Segment::Bytes(ref bytes) => out.write_all(bytes)?,
+                        Segment::File(ref path, len) => copy_exact(File::open(path)?, out, len)?,
+                    }
+                }
+                Ok(())
+            }
+            Payload::Stream(ref mut upload) => match upload.take() {
+                Some(Upload { reader, len: Some(len) }) => copy_exact(reader, out, len),
+                Some(Upload { reader, len: None }) => write_chunked(reader, out),
+                None => Err(io::Error::other("streamed request body was already sent")),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Payload {
+    /// Like `write_to`, files and readers are read off the async runtime
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&mut self, out: &mut W) -> io::Result<()> {
+        match *self {
+            Payload::Bytes(ref bytes) => out.write_all(bytes).await,
+            Payload::Multipart(ref segments) => {
+                for segment in segments {
+                    match *segment {
+                        Segment::Bytes(ref bytes) => out.write_all(bytes).await?,
+                        Segment::File(ref path, len) => {
+                            let file = tokio::fs::File::open(path).await?;
+                            let copied = tokio::io::copy(&mut file.take(len), out).await?;
+                            short_body(copied, len)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Payload::Stream(ref mut upload) => match upload.take() {
+                Some(upload) => write_stream(upload, out).await,
+                None => Err(io::Error::other("streamed request body was already sent")),
+            },
+        }
+    }
+}
+
+/// Send a blocking reader's body, read on the blocking thread pool
+#[cfg(feature = "async")]
+async fn write_stream<W: AsyncWrite + Unpin>(upload: Upload, out: &mut W) -> io::Result<()> {
+    let Upload { mut reader, len } = upload;
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<io::Result<Vec<u8>>>(4);
+    // the reader stops once the request is dropped and nobody receives
+    tokio::task::spawn_blocking(move || loop {
+        let mut buf = vec![0; CHUNK_SIZE];
+        let chunk = match reader.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => {
+                buf.truncate(n);
+                Ok(buf)
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => Err(err),
+        };
+        let failed = chunk.is_err();
+        if tx.blocking_send(chunk).is_err() || failed {
+            return;
+        }
+    });
+
+    let mut sent = 0;
+    while let Some(chunk) = rx.recv().await {
+        let chunk = chunk?;
+        match len {
+            Some(len) => {
+                let n = (len - sent).min(chunk.len() as u64) as usize;
+                out.write_all(&chunk[..n]).await?;
+            }
+            None => {
+                out.write_all(format!("{:x}{}", chunk.len(), SEP).as_bytes()).await?;
+                out.write_all(&chunk).await?;
+                out.write_all(SEP.as_bytes()).await?;
+            }
+        }
+        sent += chunk.len() as u64;
+        if len.is_some_and(|len| sent >= len) {
+            return Ok(());
+        }
+    }
+    match len {
+        Some(len) => short_body(sent, len),
+        None => out.write_all(format!("0{0}{0}", SEP).as_bytes()).await,
+    }
+}
+
+/// Copy exactly `len` bytes, the length was announced before
+fn copy_exact<R: Read, W: Write>(reader: R, out: &mut W, len: u64) -> io::Result<()> {
+    short_body(io::copy(&mut reader.take(len), out)?, len)
+}
+
+fn short_body(copied: u64, len: u64) -> io::Result<()> {
+    if copied < len {
+        let msg = format!("request body ended after {} of {} bytes", copied, len);
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+    }
+    Ok(())
+}
+
+fn write_chunked<R: Read, W: Write>(mut reader: R, out: &mut W) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        write!(out, "{:x}{}", n, SEP)?;
+        out.write_all(&buf[..n])?;
+        out.write_all(SEP.as_bytes())?;
+    }
+    write!(out, "0{0}{0}", SEP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Payload, Upload};
+
+    #[test]
+    fn test_unknown_length_is_chunked() {
+        let upload = Upload::new(Box::new(&b"hello world"[..]), None);
+        let mut payload = Payload::Stream(Some(upload));
+        let mut out = Vec::new();
+        payload.write_to(&mut out).unwrap();
+
+        assert_eq!(out, b"b\r\nhello world\r\n0\r\n\r\n");
+        // the reader is gone once sent
+        assert!(payload.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_short_reader_is_error() {
+        let upload = Upload::new(Box::new(&b"short"[..]), Some(10));
+        assert!(Payload::Stream(Some(upload)).write_to(&mut Vec::new()).is_err());
+    }
+}
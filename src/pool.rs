@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::consts::*;
+use crate::stream::Conn;
+
+/// Idle keep-alive connection waiting to be reused
+struct Idle<C> {
+    conn: C,
+    since: Instant,
+}
+
+struct Inner<C> {
+    idle: HashMap<String, Vec<Idle<C>>>,
+    max_idle: usize,
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+}
+
+/// Pool and key a connection is checked in under once its response is read
+pub type Release = (Pool, String);
+
+/// Shared pool of idle connections keyed by (scheme, host, port)
+pub struct Pool<C = Conn> {
+    inner: Arc<Mutex<Inner<C>>>,
+}
+
+impl<C> Clone for Pool<C> {
+    fn clone(&self) -> Pool<C> {
+        Pool {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C> Pool<C> {
+    pub fn new() -> Pool<C> {
+        Pool {
+            inner: Arc::new(Mutex::new(Inner {
+                idle: HashMap::new(),
+                max_idle: DEF_MAX_IDLE,
+                max_idle_per_host: DEF_MAX_IDLE_PER_HOST,
+                idle_timeout: Duration::from_secs(DEF_IDLE_TIMEOUT),
+            })),
+        }
+    }
+
+    /// Empty pool for another kind of connection with the same limits
+    #[cfg(feature = "async")]
+    pub fn with_limits_of<D>(other: &Pool<D>) -> Pool<C> {
+        let pool = Pool::new();
+        {
+            let other = other.lock();
+            let mut inner = pool.lock();
+            inner.max_idle = other.max_idle;
+            inner.max_idle_per_host = other.max_idle_per_host;
+            inner.idle_timeout = other.idle_timeout;
+        }
+        pool
+    }
+
+    pub fn set_max_idle(&self, max: usize) {
+        self.lock().max_idle = max;
+    }
+
+    pub fn set_max_idle_per_host(&self, max: usize) {
+        self.lock().max_idle_per_host = max;
+    }
+
+    pub fn set_idle_timeout(&self, timeout: Duration) {
+        self.lock().idle_timeout = timeout;
+    }
+
+    /// Take the most recently used connection for `key`, dropping expired ones
+    pub fn checkout(&self, key: &str) -> Option<C> {
+        let mut inner = self.lock();
+        inner.expire();
+        let conn = inner.idle.get_mut(key).and_then(|list| list.pop());
+        conn.map(|idle| idle.conn)
+    }
+
+    /// Return a connection to the pool, or drop it if the limits are reached
+    pub fn checkin(&self, key: String, conn: C) {
+        let mut inner = self.lock();
+        inner.expire();
+
+        let total: usize = inner.idle.values().map(|list| list.len()).sum();
+        let per_host = inner.idle.get(&key).map_or(0, |list| list.len());
+        if total >= inner.max_idle || per_host >= inner.max_idle_per_host {
+            return;
+        }
+
+        inner.idle.entry(key).or_default().push(Idle {
+            conn,
+            since: Instant::now(),
+        });
+    }
+
+    /// Number of idle connections currently held
+    pub fn idle(&self) -> usize {
+        let mut inner = self.lock();
+        inner.expire();
+        inner.idle.values().map(|list| list.len()).sum()
+    }
+
+    pub fn clear(&self) {
+        self.lock().idle.clear();
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, Inner<C>> {
+        // A panic while holding the lock can only leave stale idle entries behind
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<C> Inner<C> {
+    fn expire(&mut self) {
+        let timeout = self.idle_timeout;
+        for list in self.idle.values_mut() {
+            list.retain(|idle| idle.since.elapsed() < timeout);
+        }
+        self.idle.retain(|_, list| !list.is_empty());
+    }
+}
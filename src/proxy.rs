@@ -0,0 +1,549 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+
+use url::Url;
+
+use crate::consts::*;
+use crate::err::HttpError;
+use crate::transport::Target;
+
+/// Which requests a proxy applies to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scope {
+    All,
+    Http,
+    Https,
+}
+
+/// Protocol spoken with the proxy
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Http,
+    /// SOCKS5, resolving names on the proxy if `remote_dns`
+    Socks5 { remote_dns: bool },
+    /// SOCKS4a, names are always resolved on the proxy
+    Socks4a,
+}
+
+/// Proxy server requests are routed through
+///
+/// With an `http://` proxy, plain http requests are forwarded with an
+/// absolute-form request line and https requests are tunnelled with
+/// `CONNECT` before the TLS handshake. `socks5://` (local DNS), `socks5h://`
+/// (remote DNS) and `socks4a://` proxies open a tunnel for every request.
+/// Credentials in the proxy URL are used to authenticate.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use knock::Proxy;
+///
+/// let proxy = Proxy::all("http://proxy.example.com:3128").unwrap()
+///     .basic_auth("user", "secret")
+///     .no_proxy("localhost, .internal.example.com, 10.0.0.0/8");
+///
+/// let mut client = knock::Client::new();
+/// client.proxy(proxy);
+/// ```
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proxy {
+    url: Url,
+    kind: Kind,
+    scope: Scope,
+    credentials: Option<(String, String)>,
+    no_proxy: NoProxy,
+    /// Read from the environment by `from_env`
+    from_env: bool,
+}
+
+impl Proxy {
+    /// Proxy for every request
+    pub fn all(url: &str) -> Result<Proxy, HttpError> {
+        Proxy::new(url, Scope::All)
+    }
+
+    /// Proxy for http requests only
+    pub fn http(url: &str) -> Result<Proxy, HttpError> {
+        Proxy::new(url, Scope::Http)
+    }
+
+    /// Proxy for https requests only
+    pub fn https(url: &str) -> Result<Proxy, HttpError> {
+        Proxy::new(url, Scope::Https)
+    }
+
+    fn new(url: &str, scope: Scope) -> Result<Proxy, HttpError> {
+        let url = Url::parse(url)?;
+        let kind = match url.scheme() {
+            "http" => Kind::Http,
+            "socks5" => Kind::Socks5 { remote_dns: false },
+            "socks5h" => Kind::Socks5 { remote_dns: true },
+            "socks4a" => Kind::Socks4a,
+            scheme => return Err(HttpError::Proxy(format!("unsupported proxy scheme {:?}", scheme))),
+        };
+        if url.host_str().is_none() {
+            return Err(HttpError::Proxy(format!("proxy URL {} has no host", url)));
+        }
+
+        let credentials = match url.username() {
+            "" => None,
+            user => Some((decode(user), decode(url.password().unwrap_or("")))),
+        };
+        Ok(Proxy {
+            url,
+            kind,
+            scope,
+            credentials,
+            no_proxy: NoProxy::default(),
+            from_env: false,
+        })
+    }
+
+    /// Authenticate to the proxy with these credentials, basic auth for
+    /// http proxies and username/password (or user ID) for SOCKS
+    pub fn basic_auth(mut self, user: &str, pass: &str) -> Proxy {
+        self.credentials = Some((user.to_string(), pass.to_string()));
+        self
+    }
+
+    /// Comma separated hosts, domains (`.example.com`), IPs and CIDRs
+    /// (`10.0.0.0/8`) to connect to directly, `*` bypasses the proxy entirely
+    pub fn no_proxy(mut self, list: &str) -> Proxy {
+        self.no_proxy = NoProxy::parse(list);
+        self
+    }
+
+    /// Whether a request to `url` goes through this proxy
+    pub fn intercepts(&self, url: &Url) -> bool {
+        let scheme_matches = match self.scope {
+            Scope::All => true,
+            Scope::Http => url.scheme() == "http",
+            Scope::Https => url.scheme() == "https",
+        };
+        scheme_matches && !url.host_str().is_some_and(|host| self.no_proxy.matches(host))
+    }
+
+    /// Host of the proxy, IPv6 without brackets
+    pub fn host(&self) -> &str {
+        self.url.host_str().unwrap_or("").trim_matches(|c| c == '[' || c == ']')
+    }
+
+    pub fn port(&self) -> u16 {
+        match self.kind {
+            Kind::Http => self.url.port_or_known_default().unwrap_or(DEF_PORT),
+            _ => self.url.port().unwrap_or(DEF_SOCKS_PORT),
+        }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Value of the `Proxy-Authorization` header, if any
+    pub(crate) fn authorization(&self) -> Option<String> {
+        self.credentials.as_ref().map(|(user, pass)| basic_auth(user, pass))
+    }
+
+    /// Whether the proxy was read from the environment
+    #[cfg(feature = "async")]
+    pub(crate) fn is_from_env(&self) -> bool {
+        self.from_env
+    }
+
+    /// Whether the request itself is sent to the proxy instead of a tunnel
+    pub(crate) fn forwards(&self, url: &Url) -> bool {
+        self.kind == Kind::Http && url.scheme() == "http"
+    }
+
+    /// Prepare a fresh connection to the proxy for talking to the target
+    pub(crate) fn open(&self, stream: &mut TcpStream, target: &Target) -> Result<(), HttpError> {
+        match self.kind {
+            Kind::Http if target.is_tls() => self.tunnel(stream, target),
+            Kind::Http => Ok(()),
+            Kind::Socks5 { remote_dns } => {
+                self.socks5(stream, target, remote_dns).map_err(|err| socks_error(err, target))
+            }
+            Kind::Socks4a => self.socks4a(stream, target).map_err(|err| socks_error(err, target)),
+        }
+    }
+
+    /// Ask an http proxy to open a tunnel to the target
+    fn tunnel(&self, stream: &mut TcpStream, target: &Target) -> Result<(), HttpError> {
+        let authority = target.authority();
+        let mut request = format!("CONNECT {} {}{}", authority, HTTP_VERSION, SEP);
+        request += &format!("{}: {}{}", H_HOST, authority, SEP);
+        if let Some(auth) = self.authorization() {
+            request += &format!("{}: {}{}", H_PROXY_AUTH, auth, SEP);
+        }
+        request += SEP;
+        stream.write_all(request.as_bytes()).map_err(|err| target.connect_error(err))?;
+
+        // the server speaks only once the client hello was sent, nothing
+        // past the response head can be lost in the buffer
+        let mut reader = BufReader::new(&*stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).map_err(|err| target.connect_error(err))?;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).map_err(|err| target.connect_error(err))? == 0 {
+                return Err(HttpError::Proxy("connection closed during CONNECT".into()));
+            }
+            if line == SEP || line == "\n" {
+                break;
+            }
+        }
+
+        let code = status.split_whitespace().nth(1).unwrap_or("");
+        if !code.starts_with('2') || code.len() != 3 {
+            return Err(HttpError::Proxy(format!("CONNECT to {} refused: {}", authority, status.trim())));
+        }
+        if !reader.buffer().is_empty() {
+            return Err(HttpError::Proxy("unexpected data after CONNECT response".into()));
+        }
+        Ok(())
+    }
+
+    /// RFC 1928 handshake with RFC 1929 username/password authentication
+    fn socks5(&self, stream: &mut TcpStream, target: &Target, remote_dns: bool) -> io::Result<()> {
+        let methods: &[u8] = match self.credentials {
+            Some(_) => &[SOCKS5_NO_AUTH, SOCKS5_USER_PASS],
+            None => &[SOCKS5_NO_AUTH],
+        };
+        let mut greeting = vec![5, methods.len() as u8];
+        greeting.extend_from_slice(methods);
+        stream.write_all(&greeting)?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        match (reply[0], reply[1], &self.credentials) {
+            (5, SOCKS5_NO_AUTH, _) => {}
+            (5, SOCKS5_USER_PASS, Some((user, pass))) => {
+                if user.len() > 255 || pass.len() > 255 {
+                    return Err(socks_failure("username or password longer than 255 bytes"));
+                }
+                let mut auth = vec![1, user.len() as u8];
+                auth.extend_from_slice(user.as_bytes());
+                auth.push(pass.len() as u8);
+                auth.extend_from_slice(pass.as_bytes());
+                stream.write_all(&auth)?;
+
+                stream.read_exact(&mut reply)?;
+                if reply[1] != 0 {
+                    return Err(socks_failure("authentication rejected"));
+                }
+            }
+            (5, _, _) => return Err(socks_failure("no acceptable authentication method")),
+            _ => return Err(socks_failure("not a SOCKS5 proxy")),
+        }
+
+        let mut request = vec![5, 1, 0];
+        let host = target.host;
+        let addr = match host.parse::<IpAddr>() {
+            Ok(ip) => Some(SocketAddr::new(ip, target.port)),
+            Err(_) if remote_dns => None,
+            Err(_) => Some(
+                target
+                    .config
+                    .resolve(host, target.port)?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not resolve address"))?,
+            ),
+        };
+        match addr {
+            Some(SocketAddr::V4(addr)) => {
+                request.push(1);
+                request.extend_from_slice(&addr.ip().octets());
+            }
+            Some(SocketAddr::V6(addr)) => {
+                request.push(4);
+                request.extend_from_slice(&addr.ip().octets());
+            }
+            None => {
+                if host.len() > 255 {
+                    return Err(socks_failure("host name longer than 255 bytes"));
+                }
+                request.push(3);
+                request.push(host.len() as u8);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&target.port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut head = [0; 4];
+        stream.read_exact(&mut head)?;
+        if head[0] != 5 {
+            return Err(socks_failure("not a SOCKS5 proxy"));
+        }
+        if head[1] != 0 {
+            return Err(socks_failure(socks5_reply(head[1])));
+        }
+        // bound address, of no use to us
+        let len = match head[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0; 1];
+                stream.read_exact(&mut len)?;
+                usize::from(len[0])
+            }
+            _ => return Err(socks_failure("invalid address type in reply")),
+        };
+        let mut bound = vec![0; len + 2];
+        stream.read_exact(&mut bound)
+    }
+
+    /// SOCKS4a handshake, the user ID is taken from the credentials
+    fn socks4a(&self, stream: &mut TcpStream, target: &Target) -> io::Result<()> {
+        let mut request = vec![4, 1];
+        request.extend_from_slice(&target.port.to_be_bytes());
+        let host = target.host;
+        let ip = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => Some(ip),
+            Ok(IpAddr::V6(_)) => return Err(socks_failure("SOCKS4a cannot reach IPv6 addresses")),
+            Err(_) => None,
+        };
+        // 0.0.0.x asks the proxy to resolve the name following the user ID
+        request.extend_from_slice(&ip.unwrap_or_else(|| Ipv4Addr::new(0, 0, 0, 1)).octets());
+        if let Some((ref user, _)) = self.credentials {
+            request.extend_from_slice(user.as_bytes());
+        }
+        request.push(0);
+        if ip.is_none() {
+            request.extend_from_slice(host.as_bytes());
+            request.push(0);
+        }
+        stream.write_all(&request)?;
+
+        let mut reply = [0; 8];
+        stream.read_exact(&mut reply)?;
+        match reply[1] {
+            0x5a => Ok(()),
+            0x5b => Err(socks_failure("request rejected or failed")),
+            0x5c | 0x5d => Err(socks_failure("identd check failed")),
+            _ => Err(socks_failure("not a SOCKS4 proxy")),
+        }
+    }
+}
+
+const SOCKS5_NO_AUTH: u8 = 0;
+const SOCKS5_USER_PASS: u8 = 2;
+
+fn socks5_reply(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Protocol violations and refusals, as opposed to IO errors
+fn socks_failure(msg: &str) -> io::Error {
+    io::Error::other(SocksFailure(msg.to_string()))
+}
+
+#[derive(Debug)]
+struct SocksFailure(String);
+
+impl ::std::fmt::Display for SocksFailure {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ::std::error::Error for SocksFailure {}
+
+fn socks_error(err: io::Error, target: &Target) -> HttpError {
+    match err.get_ref().and_then(|e| e.downcast_ref::<SocksFailure>()) {
+        Some(failure) => HttpError::Proxy(format!("SOCKS connect to {} failed: {}", target.authority(), failure.0)),
+        None => target.connect_error(err),
+    }
+}
+
+/// Proxies configured through `http_proxy`, `https_proxy`, `all_proxy`
+/// and `no_proxy`, lowercase names win over uppercase ones
+pub fn from_env() -> Vec<Proxy> {
+    from_vars(|name| ::std::env::var(name).ok())
+}
+
+fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Vec<Proxy> {
+    let get = |name: &str| {
+        var(name)
+            .or_else(|| var(&name.to_uppercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+    let no_proxy = get("no_proxy").unwrap_or_default();
+
+    let scopes = [("http_proxy", Scope::Http), ("https_proxy", Scope::Https), ("all_proxy", Scope::All)];
+    scopes
+        .iter()
+        .filter_map(|&(name, scope)| {
+            let value = get(name)?;
+            let value = value.trim();
+            // `proxy:3128` is as common as `http://proxy:3128`
+            let url = match value.contains("://") {
+                true => value.to_string(),
+                false => format!("http://{}", value),
+            };
+            Proxy::new(&url, scope).ok()
+        })
+        .map(|proxy| Proxy {
+            from_env: true,
+            ..proxy.no_proxy(&no_proxy)
+        })
+        .collect()
+}
+
+/// Hosts that bypass the proxy
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NoProxy {
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Entry {
+    Any,
+    Domain(String),
+    Net(IpAddr, u8),
+}
+
+impl NoProxy {
+    pub fn parse(list: &str) -> NoProxy {
+        let entries = list
+            .split(',')
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                if entry == "*" {
+                    return Some(Entry::Any);
+                }
+                let (addr, bits) = match entry.find('/') {
+                    Some(i) => (&entry[..i], Some(&entry[i + 1..])),
+                    None => (entry, None),
+                };
+                match addr.trim_matches(|c| c == '[' || c == ']').parse::<IpAddr>() {
+                    Ok(ip) => {
+                        let max = if ip.is_ipv4() { 32 } else { 128 };
+                        let bits = match bits {
+                            Some(bits) => bits.parse::<u8>().ok().filter(|&b| b <= max)?,
+                            None => max,
+                        };
+                        Some(Entry::Net(ip, bits))
+                    }
+                    Err(_) => Some(Entry::Domain(entry.trim_start_matches('.').to_lowercase())),
+                }
+            })
+            .collect();
+        NoProxy { entries }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.trim_matches(|c| c == '[' || c == ']').to_lowercase();
+        let ip = host.parse::<IpAddr>().ok();
+        self.entries.iter().any(|entry| match *entry {
+            Entry::Any => true,
+            Entry::Domain(ref domain) => {
+                host == *domain || (host.ends_with(domain.as_str()) && host[..host.len() - domain.len()].ends_with('.'))
+            }
+            Entry::Net(net, bits) => ip.is_some_and(|ip| in_network(ip, net, bits)),
+        })
+    }
+}
+
+fn in_network(ip: IpAddr, net: IpAddr, bits: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(bits)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(bits)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+fn decode(s: &str) -> String {
+    url::form_urlencoded::parse(format!("x={}", s.replace('+', "%2B")).as_bytes())
+        .next()
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_default()
+}
+
+fn basic_auth(user: &str, pass: &str) -> String {
+    format!("Basic {}", base64(format!("{}:{}", user, pass).as_bytes()))
+}
+
+fn base64(input: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, from_vars, NoProxy};
+    use url::Url;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"Aladdin:open sesame"), "QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b""), "");
+    }
+
+    #[test]
+    fn test_no_proxy_matching() {
+        let no_proxy = NoProxy::parse("localhost, .internal.example.com,10.0.0.0/8, ::1, bad/99");
+
+        assert!(no_proxy.matches("localhost"));
+        assert!(no_proxy.matches("api.internal.example.com"));
+        assert!(no_proxy.matches("internal.example.com"));
+        assert!(!no_proxy.matches("notinternal.example.com"));
+        assert!(no_proxy.matches("10.1.2.3"));
+        assert!(!no_proxy.matches("11.1.2.3"));
+        assert!(no_proxy.matches("[::1]"));
+        assert!(NoProxy::parse("*").matches("anything"));
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let proxies = from_vars(|name| match name {
+            "http_proxy" => Some("proxy.local:3128".to_string()),
+            "HTTP_PROXY" => Some("http://ignored:1".to_string()),
+            "HTTPS_PROXY" => Some("http://secure.local:8080".to_string()),
+            "NO_PROXY" => Some("internal.example.com".to_string()),
+            _ => None,
+        });
+        let http = Url::parse("http://example.com/").unwrap();
+        let https = Url::parse("https://example.com/").unwrap();
+        let internal = Url::parse("https://api.internal.example.com/").unwrap();
+
+        assert_eq!(proxies.len(), 2);
+        assert_eq!(proxies[0].host(), "proxy.local");
+        assert!(proxies[0].intercepts(&http) && !proxies[0].intercepts(&https));
+        assert_eq!(proxies[1].port(), 8080);
+        assert!(proxies[1].intercepts(&https) && !proxies[1].intercepts(&internal));
+    }
+}
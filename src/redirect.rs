@@ -0,0 +1,77 @@
+use std::fmt;
+use std::sync::Arc;
+
+use url::Url;
+
+use crate::consts::*;
+
+/// Decides whether to follow a redirect to the URL, given the URLs visited so far
+pub type Policy = dyn Fn(&Url, &[Url]) -> bool + Send + Sync;
+
+/// What to do when a server answers with a redirect
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use knock::Redirect;
+///
+/// let mut http = knock::HTTP::new("https://example.com/old").unwrap();
+/// http.redirect(Redirect::custom(|next, _chain| next.scheme() == "https"));
+/// ```
+///
+#[derive(Clone)]
+pub enum Redirect {
+    /// Return redirect responses as they are
+    None,
+    /// Follow at most this many hops
+    Limited(usize),
+    /// Follow while the closure, given the next URL and the URLs visited
+    /// so far, returns true
+    Custom(Arc<Policy>),
+}
+
+impl Redirect {
+    pub fn custom<F>(policy: F) -> Redirect
+    where
+        F: Fn(&Url, &[Url]) -> bool + Send + Sync + 'static,
+    {
+        Redirect::Custom(Arc::new(policy))
+    }
+}
+
+impl Default for Redirect {
+    fn default() -> Redirect {
+        Redirect::Limited(DEF_MAX_REDIRECTS)
+    }
+}
+
+impl fmt::Debug for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Redirect::None => write!(f, "None"),
+            Redirect::Limited(max) => write!(f, "Limited({})", max),
+            Redirect::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+pub fn is_redirect(status: u32) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+/// Whether the request has to become a body-less GET for the next hop
+///
+/// 303 always switches to GET, 301 and 302 only for POST like browsers do.
+pub fn switches_to_get(status: u32, method: &str) -> bool {
+    match status {
+        303 => method != "GET" && method != "HEAD",
+        301 | 302 => method == "POST",
+        _ => false,
+    }
+}
+
+pub fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme()
+        && a.host_str() == b.host_str()
+        && a.port_or_known_default() == b.port_or_known_default()
+}
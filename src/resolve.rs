@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Turns a host name into the addresses to connect to
+///
+/// TLS and the `Host` header keep using the name from the URL, only the
+/// addresses dialled change.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use std::io;
+/// use std::net::SocketAddr;
+/// use knock::Resolve;
+///
+/// struct Loopback;
+///
+/// impl Resolve for Loopback {
+///     fn resolve(&self, _host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+///         Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))])
+///     }
+/// }
+///
+/// let mut client = knock::Client::new();
+/// client.resolver(Loopback);
+/// ```
+///
+pub trait Resolve: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolver of the operating system, as used by `TcpStream::connect`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+type Entries = HashMap<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+/// In-process cache of resolved addresses
+pub struct DnsCache {
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration) -> DnsCache {
+        DnsCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Cached addresses, or the ones `resolve` returns, which are then cached
+    pub fn get_or_resolve<F>(&self, host: &str, port: u16, resolve: F) -> io::Result<Vec<SocketAddr>>
+    where
+        F: FnOnce() -> io::Result<Vec<SocketAddr>>,
+    {
+        let key = (host.to_lowercase(), port);
+        {
+            let mut entries = self.lock();
+            let ttl = self.ttl;
+            entries.retain(|_, entry| entry.0.elapsed() < ttl);
+            if let Some(entry) = entries.get(&key) {
+                return Ok(entry.1.clone());
+            }
+        }
+
+        // resolve without the lock, a slow lookup must not block other hosts
+        let addrs = resolve()?;
+        if !addrs.is_empty() {
+            self.lock().insert(key, (Instant::now(), addrs.clone()));
+        }
+        Ok(addrs)
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DnsCache;
+    use std::cell::Cell;
+    use std::net::SocketAddr;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cache_expires_entries() {
+        let cache = DnsCache::new(Duration::from_millis(50));
+        let lookups = Cell::new(0);
+        let resolve = || {
+            lookups.set(lookups.get() + 1);
+            Ok(vec![SocketAddr::from(([10, 0, 0, 1], 80))])
+        };
+
+        cache.get_or_resolve("Example.com", 80, resolve).unwrap();
+        cache.get_or_resolve("example.com", 80, resolve).unwrap();
+        assert_eq!(lookups.get(), 1);
+
+        thread::sleep(Duration::from_millis(60));
+        cache.get_or_resolve("example.com", 80, resolve).unwrap();
+        assert_eq!(lookups.get(), 2);
+    }
+}
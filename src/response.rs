@@ -1,57 +1,124 @@
-use std::collections::HashMap;
-use err::HttpError;
-use consts::*;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+use serde::de::DeserializeOwned;
+use url::Url;
+use crate::body::{BodyReader, Framing};
+use crate::decode::{can_decode, content_encodings, decoder, Reader};
+use crate::err::{HttpError, JsonError};
+use crate::header::HeaderMap;
+use crate::limit::{Limit, Limited, Limits};
+use crate::method::Method;
+use crate::pool::Release;
+use crate::stream::{Conn, Timed};
+use crate::consts::*;
 
 #[derive(Debug)]
 pub struct Response {
     pub status: u32,
-    pub header: HashMap<String, String>,
-    pub body: String,
+    pub header: HeaderMap,
+    pub body: Vec<u8>,
+    /// Trailer fields of a chunked response
+    pub trailer: HeaderMap,
+    /// URLs that answered with a followed redirect, oldest first
+    pub redirects: Vec<Url>,
+    /// Whether `body` was decompressed according to `Content-Encoding`,
+    /// the header itself is left as received
+    pub decoded: bool,
 }
 
 impl Response {
     pub fn new(str: String) -> Result<Response, HttpError> {
-        let mut body = String::new();
-        let mut header: HashMap<String, String> = HashMap::new();
-        let mut status = 0;
+        Response::from_bytes(str.as_bytes())
+    }
 
-        let h_str = if str.contains(SEP) {
-            let data: Vec<&str> = str.split(&format!("{0}{0}", SEP)).collect();
-            body = data[1].to_string();
-            data[0].to_string()
-        } else {
-            str
+    /// Parse a raw response message, the body may contain arbitrary bytes
+    pub fn from_bytes(raw: &[u8]) -> Result<Response, HttpError> {
+        let end = b"\r\n\r\n";
+        let (h_str, mut body) = match raw.windows(end.len()).position(|w| w == end) {
+            Some(pos) => (&raw[..pos], raw[pos + end.len()..].to_vec()),
+            None => (raw, Vec::new()),
         };
 
+        let mut response = Response::from_head(&String::from_utf8_lossy(h_str))?;
+
+        if is_chunked(&response.header) {
+            let mut reader = BodyReader::new(&body[..], Framing::Chunked, &Limits::default());
+            let mut decoded = Vec::new();
+            reader.read_to_end(&mut decoded)?;
+            response.trailer = reader.into_trailer();
+            body = decoded;
+        } else if let Some(len) = declared_length(&response.header)? {
+            // a body shorter than announced is truncated, bytes past it are not part of it
+            let mut reader = BodyReader::new(&body[..], Framing::Length(len), &Limits::default());
+            let mut framed = Vec::new();
+            reader.read_to_end(&mut framed)?;
+            body = framed;
+        }
+
+        response.body = body;
+        Ok(response)
+    }
+
+    /// Body decoded as UTF-8, invalid sequences are replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Raw body bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Body parsed as JSON into any deserializable type
+    ///
+    /// ```rust
+    /// extern crate knock;
+    ///
+    /// use std::collections::HashMap;
+    ///
+    /// let response = knock::response::Response::new(
+    ///     "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"id\": 7}".to_string(),
+    /// ).unwrap();
+    /// let item: HashMap<String, u32> = response.json().unwrap();
+    /// assert_eq!(item["id"], 7);
+    /// ```
+    /// The error of a body that does not parse quotes its beginning
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, HttpError> {
+        serde_json::from_slice(&self.body).map_err(|err| HttpError::Json(JsonError::parse(err, &self.body)))
+    }
+
+    /// Response with status and headers parsed from the message head
+    pub(crate) fn from_head(h_str: &str) -> Result<Response, HttpError> {
+        let mut header = HeaderMap::new();
+        let mut status = 0;
+
         let tmp_vec: Vec<&str> = h_str.split(SEP).collect();
         let head = tmp_vec[0];
 
         if head.contains("HTTP/1") {
-            let vec_head: Vec<&str> = head.split(' ').collect();
-            status = vec_head[1].parse::<u32>()?;
-        }
-
-        for item in &tmp_vec {
-            if item.contains(": ") {
-                let tmp_vec: Vec<&str> = item.split(": ").collect();
-                let k = tmp_vec[0];
-                let v = tmp_vec[1];
-                if header.contains_key(k) {
-                    let value;
-                    {
-                        value = header[k].to_string();
-                    }
-                    header.insert(k.to_string(), format!("{}; {}", value, v));
-                } else {
-                    header.insert(k.to_string(), v.to_string());
-                }
+            let code = head.split_whitespace().nth(1).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("missing status code: {:?}", head))
+            })?;
+            status = code.parse::<u32>()?;
+        }
+
+        // repeated fields stay separate values, e.g. every Set-Cookie
+        for item in &tmp_vec[1..] {
+            if let Some(pos) = item.find(':') {
+                header.push(item[..pos].trim(), item[pos + 1..].trim());
             }
         }
 
         Ok(Response {
-            status: status,
-            header: header,
-            body: body,
+            status,
+            header,
+            body: Vec::new(),
+            trailer: HeaderMap::new(),
+            redirects: Vec::new(),
+            decoded: false,
         })
     }
 
@@ -59,10 +126,442 @@ impl Response {
         let status = format!("Status: {}", self.status);
         let mut header = String::new();
 
-        for (key, value) in self.header.clone() {
+        for (key, value) in &self.header {
             header = format!("{}{}{}: {}", header, SEP, key, value);
         }
 
-        format!("{0}{1}{1}{2}{1}{1}{3}", status, SEP, header, self.body)
+        format!("{0}{1}{1}{2}{1}{1}{3}", status, SEP, header, self.text())
+    }
+}
+
+/// Final response head read from a connection, the body left unread
+pub(crate) struct MessageHead {
+    pub head: String,
+    pub framing: Framing,
+    /// Whether the connection can carry another request once the body is read
+    pub reusable: bool,
+}
+
+pub(crate) fn read_message_head<R: BufRead>(
+    reader: &mut R,
+    method: &Method,
+    limits: &Limits,
+) -> io::Result<MessageHead> {
+    let mut parser = HeadParser::new(method, limits);
+    loop {
+        let input = reader.fill_buf()?;
+        let (used, head) = parser.parse(input)?;
+        reader.consume(used);
+        if let Some(head) = head {
+            return Ok(head);
+        }
+    }
+}
+
+/// Parser of the final response head, fed whatever the connection buffered
+///
+/// It does no IO itself, blocking and async connections drive the same
+/// parser. Interim 1xx responses are skipped.
+pub(crate) struct HeadParser<'a> {
+    method: &'a Method,
+    limits: &'a Limits,
+    head: Head,
+    line: Vec<u8>,
+    first: bool,
+}
+
+impl<'a> HeadParser<'a> {
+    pub fn new(method: &'a Method, limits: &'a Limits) -> HeadParser<'a> {
+        HeadParser {
+            method,
+            limits,
+            head: Head::new(),
+            line: Vec::new(),
+            first: true,
+        }
+    }
+
+    /// Take bytes from the start of `input`, empty at the end of the stream
+    ///
+    /// Returns how many bytes were used, and the head once it is complete.
+    pub fn parse(&mut self, input: &[u8]) -> io::Result<(usize, Option<MessageHead>)> {
+        if input.is_empty() {
+            let msg = if self.first && self.head.raw.is_empty() && self.line.is_empty() {
+                "connection closed before response"
+            } else {
+                "connection closed inside response head"
+            };
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+        }
+
+        let used = match input.iter().position(|&b| b == b'\n') {
+            Some(pos) => pos + 1,
+            None => input.len(),
+        };
+        self.line.extend_from_slice(&input[..used]);
+        if !self.line.ends_with(b"\n") {
+            // a line never grows past what is left of the allowed head size
+            if self.head.raw.len() + self.line.len() > self.limits.max_header_size + 2 {
+                return Err(Limit::HeaderSize(self.limits.max_header_size).into());
+            }
+            return Ok((used, None));
+        }
+
+        let line = mem::take(&mut self.line);
+        match self.head.push_line(&line, self.limits)? {
+            true => Ok((used, None)),
+            false => Ok((used, self.finish())),
+        }
+    }
+
+    /// Head ended by the blank line, unless it was an interim one
+    fn finish(&mut self) -> Option<MessageHead> {
+        let head = mem::replace(&mut self.head, Head::new());
+        self.first = false;
+
+        // Interim 1xx responses are followed by the final one
+        if (100..200).contains(&head.status) && head.status != 101 {
+            return None;
+        }
+
+        // a successful CONNECT turns the connection into a tunnel, no body follows
+        let method = self.method;
+        let tunnel = *method == Method::Connect && (200..300).contains(&head.status);
+        let framing = if *method == Method::Head || tunnel || head.status == 204 || head.status == 304 {
+            Framing::Empty
+        } else if head.chunked {
+            Framing::Chunked
+        } else {
+            match head.content_length {
+                Some(len) => Framing::Length(len),
+                None => Framing::Eof,
+            }
+        };
+
+        let keep_alive = head.keep_alive && (!head.http_10 || head.conn_keep_alive);
+        Some(MessageHead {
+            head: String::from_utf8_lossy(&head.raw).into_owned(),
+            framing,
+            reusable: keep_alive && framing != Framing::Eof && !tunnel,
+        })
+    }
+}
+
+/// Response returned once its head is read, the body is read on demand
+///
+/// ```rust,no_run
+/// extern crate knock;
+///
+/// use std::fs::File;
+/// use std::io;
+///
+/// let mut http = knock::HTTP::new("https://example.com/big.iso").unwrap();
+/// let mut response = http.get().send_streaming().unwrap();
+/// let mut file = File::create("big.iso").unwrap();
+/// io::copy(&mut response.body, &mut file).unwrap();
+/// ```
+///
+#[derive(Debug)]
+pub struct StreamingResponse {
+    pub status: u32,
+    pub header: HeaderMap,
+    /// URLs that answered with a followed redirect, oldest first
+    pub redirects: Vec<Url>,
+    /// Whether `body` yields the content decompressed according to
+    /// `Content-Encoding`, the header itself is left as received
+    pub decoded: bool,
+    pub body: BodyStream,
+}
+
+impl StreamingResponse {
+    /// Response reading its body from `conn`, which goes to `release` once
+    /// the body is read to the end if the connection can be reused
+    ///
+    /// The body is bounded by `limits` as applied to a body read into
+    /// memory if `buffered`, or to a streamed one otherwise.
+    pub(crate) fn new(
+        head: MessageHead,
+        conn: Timed<Conn>,
+        release: Option<Release>,
+        decompress: bool,
+        buffered: bool,
+        limits: &Limits,
+    ) -> Result<StreamingResponse, HttpError> {
+        let max_body = limits.body_size(buffered);
+        // refuse an announced body that is too large before reading any of it
+        if let (Framing::Length(len), Some(max)) = (head.framing, max_body) {
+            if len > max {
+                return Err(HttpError::TooLarge(Limit::BodySize(max)));
+            }
+        }
+
+        let response = Response::from_head(&head.head)?;
+        let wire = Arc::new(Mutex::new(Wire {
+            body: Some(BodyReader::new(conn, head.framing, limits)),
+            release: release.filter(|_| head.reusable),
+            trailer: HeaderMap::new(),
+        }));
+        lock(&wire).finish_if_done();
+
+        let wire_reader = WireReader(wire.clone());
+        let reader: Reader<'static> = match max_body {
+            Some(max) => Box::new(Limited::new(wire_reader, max, Limit::BodySize(max))),
+            None => Box::new(wire_reader),
+        };
+        let (reader, decoded) = decoded_reader(
+            &response.header,
+            head.framing,
+            reader,
+            decompress,
+            limits.decoded_size(buffered),
+        )?;
+
+        Ok(StreamingResponse {
+            status: response.status,
+            header: response.header,
+            redirects: Vec::new(),
+            decoded,
+            body: BodyStream { reader, wire },
+        })
+    }
+
+    /// Read the rest of the body into a buffered `Response`
+    pub fn into_response(mut self) -> io::Result<Response> {
+        let mut body = Vec::new();
+        self.body.read_to_end(&mut body)?;
+
+        Ok(Response {
+            status: self.status,
+            header: self.header,
+            body,
+            trailer: self.body.trailer(),
+            redirects: self.redirects,
+            decoded: self.decoded,
+        })
+    }
+}
+
+/// Body reader undoing `Content-Encoding`, if enabled and every coding is
+/// supported, and whether it does
+///
+/// A message without a body is left alone, e.g. the answer to HEAD or a 304
+/// announces the coding the full body would have had. The decoded body fails
+/// with `Limit::DecodedSize` past `max_decoded` bytes.
+pub(crate) fn decoded_reader<'a>(
+    header: &HeaderMap,
+    framing: Framing,
+    reader: Reader<'a>,
+    decompress: bool,
+    max_decoded: Option<u64>,
+) -> io::Result<(Reader<'a>, bool)> {
+    let empty = matches!(framing, Framing::Empty | Framing::Length(0));
+    let encodings = content_encodings(header);
+    if empty || !decompress || encodings.is_empty() || !can_decode(&encodings) {
+        return Ok((reader, false));
+    }
+    let reader = decoder(reader, &encodings)?;
+    match max_decoded {
+        Some(max) => Ok((Box::new(Limited::new(reader, max, Limit::DecodedSize(max))), true)),
+        None => Ok((reader, true)),
+    }
+}
+
+/// Body of a `StreamingResponse`
+///
+/// Once read to the end the connection goes back to the pool of the
+/// client, dropping the body before that closes the connection.
+pub struct BodyStream {
+    reader: Reader<'static>,
+    wire: Arc<Mutex<Wire>>,
+}
+
+impl BodyStream {
+    /// Trailer fields of a chunked response, set once the body is read
+    pub fn trailer(&self) -> HeaderMap {
+        lock(&self.wire).trailer.clone()
+    }
+}
+
+impl Read for BodyStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        // a decoder may stop at the end of its data before the framing ends
+        if n == 0 && !buf.is_empty() {
+            lock(&self.wire).drain()?;
+        }
+        Ok(n)
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BodyStream {{ .. }}")
+    }
+}
+
+/// Framed body read straight off the connection
+struct Wire {
+    body: Option<BodyReader<Timed<Conn>>>,
+    release: Option<Release>,
+    trailer: HeaderMap,
+}
+
+impl Wire {
+    fn finish_if_done(&mut self) {
+        if !self.body.as_ref().is_some_and(|body| body.is_done()) {
+            return;
+        }
+        let (conn, trailer) = self.body.take().expect("checked above").into_parts();
+        self.trailer = trailer;
+        if let Some((pool, key)) = self.release.take() {
+            pool.checkin(key, conn.into_inner());
+        }
+    }
+
+    /// Read what is left of the framing, at most a little of it
+    fn drain(&mut self) -> io::Result<()> {
+        let mut buf = [0; 1024];
+        for _ in 0..DRAIN_READS {
+            let n = match self.body {
+                Some(ref mut body) => body.read(&mut buf)?,
+                None => 0,
+            };
+            if n == 0 {
+                break;
+            }
+        }
+        self.finish_if_done();
+        Ok(())
+    }
+}
+
+/// How many reads are spent on leftovers before the connection is given up
+const DRAIN_READS: usize = 8;
+
+struct WireReader(Arc<Mutex<Wire>>);
+
+impl Read for WireReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut wire = lock(&self.0);
+        let n = match wire.body {
+            Some(ref mut body) => body.read(buf)?,
+            None => 0,
+        };
+        wire.finish_if_done();
+        Ok(n)
+    }
+}
+
+fn lock(wire: &Mutex<Wire>) -> MutexGuard<'_, Wire> {
+    wire.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Length announced by `Content-Length`, every field must agree
+fn declared_length(header: &HeaderMap) -> io::Result<Option<u64>> {
+    let values = header.get_all(H_CLEN);
+    if values.is_empty() {
+        return Ok(None);
+    }
+    content_length(&values.join(",")).map(Some)
+}
+
+fn is_chunked(header: &HeaderMap) -> bool {
+    header
+        .get_all(H_TENC)
+        .iter()
+        .any(|val| val.to_ascii_lowercase().ends_with("chunked"))
+}
+
+struct Head {
+    raw: Vec<u8>,
+    status: u32,
+    content_length: Option<u64>,
+    chunked: bool,
+    keep_alive: bool,
+    http_10: bool,
+    conn_keep_alive: bool,
+    fields: usize,
+}
+
+impl Head {
+    fn new() -> Head {
+        Head {
+            raw: Vec::new(),
+            status: 0,
+            content_length: None,
+            chunked: false,
+            keep_alive: true,
+            http_10: false,
+            conn_keep_alive: false,
+            fields: 0,
+        }
+    }
+
+    /// Add a line of the head, false for the blank line ending it
+    fn push_line(&mut self, line: &[u8], limits: &Limits) -> io::Result<bool> {
+        let text = String::from_utf8_lossy(line).into_owned();
+        let text = text.trim_end();
+        if text.is_empty() {
+            return Ok(false);
+        }
+        self.raw.extend_from_slice(line);
+        if self.raw.len() > limits.max_header_size {
+            return Err(Limit::HeaderSize(limits.max_header_size).into());
+        }
+
+        if self.status == 0 {
+            let mut parts = text.split(' ');
+            self.http_10 = parts.next() == Some("HTTP/1.0");
+            self.status = parts.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+            return Ok(true);
+        }
+
+        self.fields += 1;
+        if self.fields > limits.max_headers {
+            return Err(Limit::HeaderCount(limits.max_headers).into());
+        }
+
+        if let Some(pos) = text.find(':') {
+            let name = text[..pos].trim();
+            let value = text[pos + 1..].trim();
+            if name.eq_ignore_ascii_case(H_CLEN) {
+                let length = content_length(value)?;
+                if self.content_length.is_some_and(|known| known != length) {
+                    return Err(invalid_length(text));
+                }
+                self.content_length = Some(length);
+            } else if name.eq_ignore_ascii_case(H_TENC) {
+                self.chunked = value.to_ascii_lowercase().ends_with("chunked");
+            } else if name.eq_ignore_ascii_case(H_CONN) {
+                let value = value.to_ascii_lowercase();
+                if value.contains(DEF_CONN) {
+                    self.keep_alive = false;
+                }
+                if value.contains(DEF_KEEP_ALIVE) {
+                    self.conn_keep_alive = true;
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Value of a `Content-Length` field, a list of identical values counts once
+fn content_length(value: &str) -> io::Result<u64> {
+    let mut length = None;
+    for item in value.split(',').map(str::trim) {
+        if item.is_empty() || !item.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid_length(value));
+        }
+        let item = item.parse().map_err(|_| invalid_length(value))?;
+        if length.is_some_and(|known| known != item) {
+            return Err(invalid_length(value));
+        }
+        length = Some(item);
     }
+    length.ok_or_else(|| invalid_length(value))
+}
+
+fn invalid_length(value: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid or conflicting content length: {:?}", value))
 }
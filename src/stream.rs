@@ -0,0 +1,152 @@
+use std::borrow::BorrowMut;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+use native_tls::TlsStream;
+#[cfg(feature = "rustls")]
+use rustls::{ClientConnection, StreamOwned};
+
+use crate::transport::Connection;
+
+/// Buffered connection as kept in the pool
+pub type Conn = BufReader<Box<dyn Connection>>;
+
+/// Connected socket, either plain TCP or wrapped in TLS
+pub enum Stream {
+    Plain(TcpStream),
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(feature = "rustls")]
+    Rustls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Stream {
+    fn tcp(&self) -> &TcpStream {
+        match *self {
+            Stream::Plain(ref s) => s,
+            #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+            Stream::Tls(ref s) => s.get_ref(),
+            #[cfg(feature = "rustls")]
+            Stream::Rustls(ref s) => s.get_ref(),
+        }
+    }
+}
+
+impl Connection for Stream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_write_timeout(timeout)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut s) => s.read(buf),
+            #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+            Stream::Tls(ref mut s) => s.read(buf),
+            #[cfg(feature = "rustls")]
+            Stream::Rustls(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut s) => s.write(buf),
+            #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+            Stream::Tls(ref mut s) => s.write(buf),
+            #[cfg(feature = "rustls")]
+            Stream::Rustls(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Plain(ref mut s) => s.flush(),
+            #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+            Stream::Tls(ref mut s) => s.flush(),
+            #[cfg(feature = "rustls")]
+            Stream::Rustls(ref mut s) => s.flush(),
+        }
+    }
+}
+
+/// Shorter of a per-operation timeout and the time left until a deadline
+///
+/// Fails with `TimedOut` once the deadline has passed.
+pub fn remaining(timeout: Option<Duration>, deadline: Option<Instant>) -> io::Result<Option<Duration>> {
+    let left = match deadline {
+        Some(deadline) => {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "deadline elapsed"));
+            }
+            Some(deadline - now)
+        }
+        None => None,
+    };
+
+    Ok(match (timeout, left) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    })
+}
+
+/// Buffered connection whose reads are bounded by an idle timeout and a deadline
+///
+/// Either borrows the connection or owns it, as a streamed body does.
+pub struct Timed<C> {
+    conn: C,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl<C: BorrowMut<Conn>> Timed<C> {
+    pub fn new(conn: C, timeout: Option<Duration>, deadline: Option<Instant>) -> Timed<C> {
+        Timed {
+            conn,
+            timeout,
+            deadline,
+        }
+    }
+
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+
+    fn arm(&mut self) -> io::Result<()> {
+        let timeout = remaining(self.timeout, self.deadline)?;
+        self.conn.borrow_mut().get_ref().set_read_timeout(timeout)
+    }
+}
+
+impl<C: BorrowMut<Conn>> Read for Timed<C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.conn.borrow_mut().buffer().is_empty() {
+            self.arm()?;
+        }
+        self.conn.borrow_mut().read(buf)
+    }
+}
+
+impl<C: BorrowMut<Conn>> BufRead for Timed<C> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.conn.borrow_mut().buffer().is_empty() {
+            self.arm()?;
+        }
+        self.conn.borrow_mut().fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.conn.borrow_mut().consume(amt)
+    }
+}
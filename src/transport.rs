@@ -0,0 +1,220 @@
+use std::io;
+use std::io::prelude::*;
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use url::{Host, Url};
+
+use crate::config::Config;
+use crate::dial::happy_eyeballs;
+use crate::proxy::Proxy;
+use crate::consts::*;
+use crate::err::{HttpError, Timeout};
+use crate::stream::{remaining, Stream};
+use crate::tls::tls_transport;
+
+/// Byte stream to a server, as opened by a `Transport`
+pub trait Connection: Read + Write + Send {
+    /// Bound blocking reads, connections without a socket may ignore it
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Bound blocking writes, connections without a socket may ignore it
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens connections for requests
+///
+/// `HTTP` serializes the request, writes it to the connection and parses the
+/// response read back from it, a transport only has to reach the server.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// use knock::{Connection, HttpError, Target, TcpTransport, Transport};
+///
+/// struct Logging(TcpTransport);
+///
+/// impl Transport for Logging {
+///     fn connect(&self, target: &Target) -> Result<Box<dyn Connection>, HttpError> {
+///         println!("connecting to {}", target.authority());
+///         self.0.connect(target)
+///     }
+/// }
+///
+/// let mut http = knock::HTTP::new("https://example.com/api/date").unwrap();
+/// http.transport(Logging(TcpTransport)).get().send();
+/// ```
+///
+pub trait Transport: Send + Sync {
+    fn connect(&self, target: &Target) -> Result<Box<dyn Connection>, HttpError>;
+}
+
+/// Server a connection is requested for
+pub struct Target<'a> {
+    pub scheme: &'a str,
+    /// Domain (IDNA encoded) or IP address, IPv6 without brackets
+    pub host: &'a str,
+    pub port: u16,
+    /// Proxy to reach the server through, if any
+    pub proxy: Option<&'a Proxy>,
+    pub(crate) config: &'a Config,
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl<'a> Target<'a> {
+    /// Whether the connection has to be wrapped in TLS
+    pub fn is_tls(&self) -> bool {
+        self.scheme == "https"
+    }
+
+    /// `host:port`, with brackets around IPv6 addresses
+    pub fn authority(&self) -> String {
+        authority(self.host, self.port)
+    }
+
+    /// Time left for connecting, considering the connect timeout and deadline
+    pub fn connect_timeout(&self) -> Result<Option<Duration>, HttpError> {
+        remaining(self.config.connect_timeout, self.deadline)
+            .map_err(|err| self.connect_error(err))
+    }
+
+    pub(crate) fn connect_error(&self, err: io::Error) -> HttpError {
+        HttpError::timed_out(err, Timeout::Connect, self.deadline)
+    }
+}
+
+/// Default transport over TCP, with TLS for https
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpTransport;
+
+impl Transport for TcpTransport {
+    fn connect(&self, target: &Target) -> Result<Box<dyn Connection>, HttpError> {
+        let stream = match target.proxy {
+            Some(proxy) => {
+                let mut stream = tcp_connect(target, proxy.host(), proxy.port())?;
+                proxy.open(&mut stream, target)?;
+                stream
+            }
+            None => tcp_connect(target, target.host, target.port)?,
+        };
+
+        if target.is_tls() {
+            Ok(Box::new(tls_transport(target, stream)?))
+        } else {
+            Ok(Box::new(Stream::Plain(stream)))
+        }
+    }
+}
+
+/// Open a TCP connection honouring the connect timeout and deadline
+///
+/// The read and write timeouts are applied right away so that a TLS
+/// handshake cannot hang either.
+fn tcp_connect(target: &Target, host: &str, port: u16) -> Result<TcpStream, HttpError> {
+    let connect_err = |err| target.connect_error(err);
+    let config = target.config;
+
+    let timeout = target.connect_timeout()?;
+    let addrs = config.resolve(host, port).map_err(connect_err)?;
+    let delay = config
+        .happy_eyeballs_delay
+        .unwrap_or_else(|| Duration::from_millis(DEF_HAPPY_EYEBALLS_DELAY));
+    let stream = match happy_eyeballs(addrs, delay, timeout) {
+        Ok(stream) => stream,
+        // the connect timeout, or the overall deadline if that is what ran out
+        Err(ref err) if err.is_timeout() => return Err(connect_err(io::ErrorKind::TimedOut.into())),
+        Err(err) => return Err(HttpError::Connect(err)),
+    };
+
+    stream.set_read_timeout(remaining(config.read_timeout, target.deadline).map_err(connect_err)?)?;
+    stream.set_write_timeout(remaining(config.write_timeout, target.deadline).map_err(connect_err)?)?;
+    Ok(stream)
+}
+
+/// Transport over a Unix domain socket, whatever the host of the URL
+///
+/// The URL still provides the `Host` header and request path. TLS is not
+/// available over Unix sockets.
+///
+/// ```rust
+/// extern crate knock;
+///
+/// let mut http = knock::HTTP::new("http://localhost/v1.41/info").unwrap();
+/// http.unix_socket("/var/run/docker.sock").get().send();
+/// ```
+///
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct UnixTransport {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixTransport {
+    pub fn new<P: Into<PathBuf>>(path: P) -> UnixTransport {
+        UnixTransport { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn connect(&self, target: &Target) -> Result<Box<dyn Connection>, HttpError> {
+        if target.is_tls() {
+            let err = io::Error::new(io::ErrorKind::InvalidInput, "TLS over Unix sockets is not supported");
+            return Err(err.into());
+        }
+        let connect_err = |err| target.connect_error(err);
+        let config = target.config;
+
+        let stream = UnixStream::connect(&self.path).map_err(connect_err)?;
+        stream.set_read_timeout(remaining(config.read_timeout, target.deadline).map_err(connect_err)?)?;
+        stream.set_write_timeout(remaining(config.write_timeout, target.deadline).map_err(connect_err)?)?;
+        Ok(Box::new(stream))
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// Host of the URL to connect to, IPv6 literals without brackets
+pub fn dial_host(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Ipv6(ip) => Some(ip.to_string()),
+        host => Some(host.to_string()),
+    }
+}
+
+pub fn authority(host: &str, port: u16) -> String {
+    match host.contains(':') {
+        true => format!("[{}]:{}", host, port),
+        false => format!("{}:{}", host, port),
+    }
+}
+
+/// Port to connect to for a scheme without explicit port
+pub fn default_port(scheme: &str) -> u16 {
+    match scheme {
+        "https" => DEF_SSL_PORT,
+        _ => DEF_PORT,
+    }
+}